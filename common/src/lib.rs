@@ -0,0 +1,16 @@
+//! Definitions shared by the host and the configurator.
+
+#![warn(clippy::pedantic)]
+#![deny(clippy::unwrap_in_result)]
+
+/// Returns whether `name` can be used as a profile name.
+///
+/// Profile names appear in paths and in the wrapper scripts written by the configurator, so they
+/// are limited to ASCII letters, digits, `-` and `_`.
+#[must_use]
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
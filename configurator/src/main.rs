@@ -7,12 +7,25 @@ use std::{
 };
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use directories::ProjectDirs;
+use noematic_common::is_valid_profile_name;
 use serde_json::Value;
 
 use host_manifest::{Chromium, Firefox, ManifestPath};
 
 const HOST_BINARY_NAME: &str = "noematic";
+const MSG_MISSING_HOME_DIR: &str = "Missing home directory";
+const MSG_INVALID_PROFILE_NAME: &str = "Invalid profile name";
+
+/// The directory, relative to the host's data directory, holding profile wrapper scripts.
+const WRAPPERS_DIR: &str = "wrappers";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Browser {
+    Firefox,
+    Chromium,
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -20,6 +33,21 @@ struct Args {
     /// Path to binary
     #[arg(short, long)]
     binary: Option<PathBuf>,
+    /// Point the manifests at a wrapper which runs the host with this profile
+    #[arg(short, long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Only write manifests for these browsers
+    #[arg(long, value_enum, value_delimiter = ',')]
+    browser: Vec<Browser>,
+    /// Write the Chromium manifest into this user data directory, instead of for all profiles
+    #[arg(long, value_name = "DIR")]
+    chromium_user_data_dir: Option<PathBuf>,
+}
+
+impl Args {
+    fn includes(&self, browser: Browser) -> bool {
+        self.browser.is_empty() || self.browser.contains(&browser)
+    }
 }
 
 fn default_prefix() -> Result<PathBuf, io::Error> {
@@ -50,20 +78,68 @@ fn write(path: impl AsRef<Path>, value: &Value) -> Result<(), Error> {
     fs::write(path, json).map_err(Into::into)
 }
 
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Writes a script which runs the host at `binary` with `profile`, returning its path.
+///
+/// Browsers pass their own arguments to the host and cannot be told to add any, so a profile is
+/// selected by pointing the manifest at the script instead.
+fn write_wrapper(binary: &Path, profile: &str) -> Result<PathBuf, Error> {
+    if !is_valid_profile_name(profile) {
+        return Err(Error::msg(format!("{MSG_INVALID_PROFILE_NAME}: {profile}")));
+    }
+    let project_dirs = ProjectDirs::from("com.github", "henrytill", "noematic")
+        .ok_or_else(|| Error::msg(MSG_MISSING_HOME_DIR))?;
+    let dir = project_dirs.data_dir().join(WRAPPERS_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{HOST_BINARY_NAME}-{profile}"));
+    let binary = binary.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Binary path is not valid UTF-8",
+        )
+    })?;
+    let script = format!(
+        "#!/bin/sh\nexec {} --profile {} \"$@\"\n",
+        shell_quote(binary),
+        shell_quote(profile)
+    );
+    fs::write(&path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(path)
+}
+
 fn main() -> Result<(), Error> {
-    let args = Args::parse();
+    let mut args = Args::parse();
 
-    let path = if let Some(path) = args.binary {
+    let path = if let Some(path) = args.binary.take() {
         fs::canonicalize(path)?
     } else {
         let prefix = default_prefix()?;
         default_binary_path(prefix)
     };
 
+    let path = if let Some(profile) = &args.profile {
+        let wrapper = write_wrapper(&path, profile)?;
+        println!(
+            "Wrapper for profile {profile} written to: {}",
+            wrapper.display()
+        );
+        wrapper
+    } else {
+        path
+    };
+
     let default_dir = env::current_dir()?;
     println!("default_dir: {}", default_dir.display());
 
-    {
+    if args.includes(Browser::Firefox) {
         let manifest = serde_json::to_value(Firefox::new(&path))?;
         let manifest_path = ManifestPath::for_platform(Firefox::path(), &default_dir);
         write(&manifest_path, &manifest)?;
@@ -77,9 +153,12 @@ fn main() -> Result<(), Error> {
         );
     }
 
-    {
+    if args.includes(Browser::Chromium) {
         let manifest = serde_json::to_value(Chromium::new(&path))?;
-        let manifest_path = ManifestPath::for_platform(Chromium::path(), &default_dir);
+        let manifest_path = match &args.chromium_user_data_dir {
+            Some(dir) => Chromium::user_data_dir_path(dir),
+            None => ManifestPath::for_platform(Chromium::path(), &default_dir),
+        };
         write(&manifest_path, &manifest)?;
         println!(
             "Chromium host manifest written to: {}",
@@ -220,5 +299,14 @@ mod host_manifest {
                 default: default.into_iter().collect::<PathBuf>(),
             }
         }
+
+        /// Chromium also reads manifests from the user data directory given by `--user-data-dir`,
+        /// which are used only by the browser profiles within it.
+        pub fn user_data_dir_path(user_data_dir: impl AsRef<Path>) -> PathBuf {
+            let mut ret = PathBuf::from(user_data_dir.as_ref());
+            ret.push("NativeMessagingHosts");
+            ret.push(file());
+            ret
+        }
     }
 }
@@ -2,7 +2,7 @@ import { NATIVE_MESSAGING_HOST, SCHEMA_VERSION } from '../common/common.mjs';
 import { MessageCollector } from '../common/message-collector.mjs';
 
 /**
- * @typedef {Map<import('../common/types.js').UUID, MessageCollector>} ResponderMap
+ * @typedef {Map<import('../common/types.js').CorrelationId, MessageCollector>} ResponderMap
  */
 
 /**
@@ -17,12 +17,82 @@ const portOnMessageListener = (responderMap, message) => {
     console.error('No collector for correlation id', correlationId);
     return;
   }
+  // A cancelled search sends no more responses, so it is answered with the cancelResponse, and
+  // its collector removed.
+  if (message.action === 'cancelResponse' && message.payload.status === 'cancelled') {
+    const cancelled = message.payload.correlationId;
+    responderMap.get(cancelled)?.responder(message);
+    responderMap.delete(cancelled);
+  }
   const collected = collector.push(message);
   if (collected) {
     responderMap.delete(correlationId);
   }
 };
 
+/**
+ * The length of the parts in which the text of a large page is sent to the host, which is well
+ * below the size of the largest message the browser will send, even when escaped.
+ */
+const SAVE_CHUNK_LENGTH = 4 * 1024 * 1024;
+
+/**
+ * Sends a request to the host, resolving with its response.
+ *
+ * @param {ResponderMap} responderMap
+ * @param {chrome.runtime.Port} hostPort
+ * @param {any} message
+ * @returns {Promise<any>}
+ */
+const request = (responderMap, hostPort, message) =>
+  new Promise((resolve) => {
+    const correlationId = crypto.randomUUID();
+    responderMap.set(correlationId, new MessageCollector(correlationId, resolve));
+    const outgoing = { version: SCHEMA_VERSION, ...message, correlationId };
+    console.debug('request', outgoing);
+    hostPort.postMessage(outgoing);
+  });
+
+/**
+ * Saves a page whose text is too large for one message, by sending the text in parts.
+ *
+ * @param {ResponderMap} responderMap
+ * @param {chrome.runtime.Port} hostPort
+ * @param {{url: string, title: string, innerText: string}} payload
+ * @returns {Promise<any>}
+ */
+const saveInChunks = async (responderMap, hostPort, { url, title, innerText }) => {
+  const begin = await request(responderMap, hostPort, {
+    action: 'saveBeginRequest',
+    payload: { url, title },
+  });
+  if (begin.action !== 'saveBeginResponse') {
+    return begin;
+  }
+  const uploadId = begin.payload.uploadId;
+  let index = 0;
+  for (let start = 0; start < innerText.length; index++) {
+    let end = Math.min(start + SAVE_CHUNK_LENGTH, innerText.length);
+    // Surrogate pairs are not split, as the host only accepts valid Unicode.
+    const last = innerText.charCodeAt(end - 1);
+    if (end < innerText.length && last >= 0xd800 && last <= 0xdbff) {
+      end -= 1;
+    }
+    const response = await request(responderMap, hostPort, {
+      action: 'saveChunkRequest',
+      payload: { uploadId, index, text: innerText.slice(start, end) },
+    });
+    if (response.action !== 'saveChunkResponse') {
+      return response;
+    }
+    start = end;
+  }
+  return request(responderMap, hostPort, {
+    action: 'saveCommitRequest',
+    payload: { uploadId, count: index },
+  });
+};
+
 /**
  * @param {ResponderMap} responderMap
  * @param {chrome.runtime.Port} hostPort
@@ -32,7 +102,12 @@ const portOnMessageListener = (responderMap, message) => {
  * @returns {boolean | undefined}
  */
 const runtimeOnMessageListener = (responderMap, hostPort, message, _sender, sendResponse) => {
-  const correlationId = crypto.randomUUID();
+  if (message.action === 'saveRequest' && message.payload.innerText.length > SAVE_CHUNK_LENGTH) {
+    saveInChunks(responderMap, hostPort, message.payload).then(sendResponse);
+    return true;
+  }
+  // A sender that may cancel its request chooses the correlation id itself.
+  const correlationId = message.correlationId ?? crypto.randomUUID();
   message.correlationId = correlationId;
   console.debug('request', message);
   responderMap.set(correlationId, new MessageCollector(correlationId, sendResponse));
@@ -12,6 +12,8 @@ export class MessageCollector {
   responder;
   /** @type {Response[]} */
   responses = [];
+  /** @type {string[]} */
+  chunks = [];
 
   /**
    * @param {UUID} correlationId
@@ -27,6 +29,15 @@ export class MessageCollector {
    * @returns {boolean}
    */
   push(response) {
+    if (response.action === 'responseChunk') {
+      const { index, count, data } = response.payload;
+      this.chunks[index] = data;
+      if (index < count - 1) {
+        return false;
+      }
+      response = JSON.parse(this.chunks.join(''));
+      this.chunks = [];
+    }
     this.responses.push(response);
     console.debug('response', response);
     return this.collect();
@@ -49,7 +60,8 @@ export class MessageCollector {
         this.responder(head);
         return true;
       }
-      case 'searchResponseHeader': {
+      case 'searchResponseHeader':
+      case 'listResponseHeader': {
         const pageLength = head.payload.pageLength;
         if (pageLength !== this.responses.length - 1) {
           return false;
@@ -60,8 +72,13 @@ export class MessageCollector {
         this.responses = [];
         return true;
       }
-      case 'searchResponseSite': {
-        throw new Error('collect: top was searchResponseSite');
+      case 'searchResponseSite':
+      case 'listResponseSite': {
+        throw new Error(`collect: top was ${head.action}`);
+      }
+      default: {
+        this.responder(head);
+        return true;
       }
     }
   }
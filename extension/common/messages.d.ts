@@ -0,0 +1,672 @@
+// Generated from host/src/message.rs by `noematic schema --typescript`. Do not edit.
+
+export type AddAnnotationRequestPayload = {
+  note: Note;
+  quote?: Quote | null;
+  url: Url;
+};
+
+/** `annotation` is `None` if there is no saved site with the given url. */
+export type AddAnnotationResponsePayload = {
+  annotation?: Annotation | null;
+};
+
+export type Annotation = {
+  createdAt: Timestamp;
+  id: AnnotationId;
+  note: Note;
+  quote?: Quote | null;
+  updatedAt: Timestamp;
+  url: Url;
+};
+
+export type AnnotationId = number;
+
+/** `path` must be absolute, and must not name an existing file. */
+export type BackupRequestPayload = {
+  path: string;
+};
+
+export type BackupResponsePayload = Record<string, never>;
+
+/** `correlationId` is that of the search to cancel, rather than of the `cancelRequest`. */
+export type CancelRequestPayload = {
+  correlationId: CorrelationId;
+};
+
+/** `correlationId` is that of the search. */
+export type CancelResponsePayload = {
+  correlationId: CorrelationId;
+  status: CancelStatus;
+};
+
+/** What became of the search that a `cancelRequest` was for. */
+export type CancelStatus =
+  /** The search was stopped, and sends no more responses. */
+  | 'cancelled'
+  /**
+   * There is no search with the correlation id that has yet to finish, so all of its
+   * responses are sent.
+   */
+  | 'notRunning';
+
+export type CapabilitiesRequestPayload = Record<string, never>;
+
+/**
+ * What this build of the host supports, so that clients can adapt to it.
+ *
+ * `features` are the optional features the host was built with, such as `encryption`.
+ */
+export type CapabilitiesResponsePayload = {
+  features: string[];
+  ftsTokenizer: string;
+  hostVersion: string;
+  limits: Limits;
+  requestActions: string[];
+  schemaVersion: SemVer;
+};
+
+/** How a site was changed, as reported to subscribers. */
+export type Change = 'saved' | 'removed' | 'restored' | 'deleted' | 'statusChanged' | 'annotated';
+
+export type ConfigRequestPayload = Record<string, never>;
+
+export type CorrelationId = string;
+
+export type DomainCount = {
+  count: number;
+  domain: string;
+};
+
+export type EditAnnotationRequestPayload = {
+  id: AnnotationId;
+  note: Note;
+  quote?: Quote | null;
+};
+
+/** `annotation` is `None` if there is no annotation with the given id. */
+export type EditAnnotationResponsePayload = {
+  annotation?: Annotation | null;
+};
+
+export type EmptyTrashRequestPayload = Record<string, never>;
+
+export type EmptyTrashResponsePayload = {
+  removed: number;
+};
+
+/** A request that was understood, but could not be handled. */
+export type ErrorResponsePayload =
+  /** The request's version, or every version offered in a `helloRequest`, is not supported. */
+  | {
+      kind: 'incompatibleVersion';
+      message: string;
+      supported: VersionRange;
+    }
+  /**
+   * The request was larger than the host reads, and was skipped. Its correlation id is found
+   * at either end of a JSON request, and is otherwise empty.
+   */
+  | {
+      kind: 'requestTooLarge';
+      maxSize: number;
+      message: string;
+      size: number;
+    }
+  /**
+   * There is no upload with the id that the client started, or some of its chunks are
+   * missing. Uploads that are not committed are discarded after an hour.
+   */
+  | {
+      kind: 'invalidUpload';
+      message: string;
+      uploadId: UploadId;
+    }
+  /**
+   * The request could not be parsed, or some of its fields are invalid. `fields` is empty if
+   * it could not be parsed, in which case `message` says why.
+   */
+  | {
+      fields: FieldError[];
+      kind: 'invalidRequest';
+      message: string;
+    };
+
+export type Excerpt = string;
+
+/**
+ * A field of a request whose value is not accepted.
+ *
+ * `field` is the name of the field in the request's payload.
+ */
+export type FieldError = {
+  field: string;
+  message: string;
+};
+
+export type FtsProblem = {
+  message: string;
+  table: string;
+};
+
+export type GetRequestPayload = {
+  url: Url;
+};
+
+/** `site` is `None` if there is no saved site with the given url. */
+export type GetResponsePayload = {
+  site?: Site | null;
+};
+
+/**
+ * `capabilities` are the optional features of the protocol that the client supports. They are
+ * accepted so that clients can offer them, but the host does not yet depend on any.
+ */
+export type HelloRequestPayload = {
+  capabilities?: string[];
+  versions: VersionRange;
+};
+
+/**
+ * `version` is the version the client should use, and `versions` and `capabilities` are those
+ * of the host.
+ */
+export type HelloResponsePayload = {
+  capabilities: string[];
+  version: MessageVersion;
+  versions: VersionRange;
+};
+
+export type InnerText = string;
+
+/** Sizes are in bytes. */
+export type Limits = {
+  maxPageLength: number;
+  maxRequestSize: number;
+  maxResponseSize: number;
+};
+
+export type ListAnnotationsRequestPayload = {
+  url: Url;
+};
+
+export type ListAnnotationsResponsePayload = {
+  annotations: Annotation[];
+};
+
+export type ListRequestPayload = {
+  pageLength: number;
+  pageNum: number;
+  status?: Status | null;
+  /** List the sites in the trash instead of the saved ones. */
+  trashed?: boolean;
+};
+
+export type ListResponseHeaderPayload = {
+  hasMore: boolean;
+  pageLength: number;
+  pageNum: number;
+};
+
+export type ListResponseSitePayload = {
+  createdAt: Timestamp;
+  status: Status;
+  statusUpdatedAt?: Timestamp | null;
+  title: Title;
+  updatedAt: Timestamp;
+  url: Url;
+};
+
+export type MaintenanceFindings = {
+  /** Problems reported by the FTS5 `integrity-check` command. */
+  ftsIntegrityCheck: FtsProblem[];
+  healthy: boolean;
+  /** The number of rows in `sites_fts`. */
+  indexedSites: number;
+  /** Problems reported by `PRAGMA integrity_check`. */
+  integrityCheck: string[];
+  /** The number of rows in `sites`. */
+  sites: number;
+  /** The number of sites with text but no passages. */
+  sitesWithoutPassages: number;
+  /** The number of sites missing from `sites_fts`. */
+  unindexedSites: number;
+};
+
+export type MaintenanceRequestPayload = {
+  /** Merge the full-text indexes and `VACUUM` the database. */
+  optimize?: boolean;
+  /** Recreate the full-text indexes from the saved sites. */
+  rebuild?: boolean;
+};
+
+/** `after` is present if any repairs were requested, and describes the database after they ran. */
+export type MaintenanceResponsePayload = {
+  after?: MaintenanceFindings | null;
+  before: MaintenanceFindings;
+  optimized: boolean;
+  rebuilt: boolean;
+};
+
+export type MessageVersion = SemVer;
+
+export type Note = string;
+
+/**
+ * Offsets of a passage within a site's `inner_text`, in UTF-16 code units, as JavaScript indexes
+ * strings.
+ */
+export type PassageRange = {
+  end: number;
+  start: number;
+};
+
+export type Query = string;
+
+/**
+ * A selection of a site's `inner_text`, delimited by offsets in UTF-16 code units, as JavaScript
+ * indexes strings.
+ */
+export type Quote = {
+  end: number;
+  start: number;
+  text: Excerpt;
+};
+
+export type RemoveAnnotationRequestPayload = {
+  id: AnnotationId;
+};
+
+export type RemoveAnnotationResponsePayload = Record<string, never>;
+
+export type RemoveRequestPayload = {
+  url: Url;
+};
+
+export type RemoveResponsePayload = Record<string, never>;
+
+export type Request = {
+  correlationId: CorrelationId;
+  version: MessageVersion;
+} & (
+  | {
+      action: 'saveRequest';
+      payload: SaveRequestPayload;
+    }
+  | {
+      action: 'removeRequest';
+      payload: RemoveRequestPayload;
+    }
+  | {
+      action: 'searchRequest';
+      payload: SearchRequestPayload;
+    }
+  | {
+      action: 'addAnnotationRequest';
+      payload: AddAnnotationRequestPayload;
+    }
+  | {
+      action: 'editAnnotationRequest';
+      payload: EditAnnotationRequestPayload;
+    }
+  | {
+      action: 'removeAnnotationRequest';
+      payload: RemoveAnnotationRequestPayload;
+    }
+  | {
+      action: 'listAnnotationsRequest';
+      payload: ListAnnotationsRequestPayload;
+    }
+  | {
+      action: 'setStatusRequest';
+      payload: SetStatusRequestPayload;
+    }
+  | {
+      action: 'listRequest';
+      payload: ListRequestPayload;
+    }
+  | {
+      action: 'restoreRequest';
+      payload: RestoreRequestPayload;
+    }
+  | {
+      action: 'emptyTrashRequest';
+      payload: EmptyTrashRequestPayload;
+    }
+  | {
+      action: 'statsRequest';
+      payload: StatsRequestPayload;
+    }
+  | {
+      action: 'backupRequest';
+      payload: BackupRequestPayload;
+    }
+  | {
+      action: 'maintenanceRequest';
+      payload: MaintenanceRequestPayload;
+    }
+  | {
+      action: 'configRequest';
+      payload: ConfigRequestPayload;
+    }
+  | {
+      action: 'subscribeRequest';
+      payload: SubscribeRequestPayload;
+    }
+  | {
+      action: 'getRequest';
+      payload: GetRequestPayload;
+    }
+  /** Accepted whatever its version, so that clients can find a version to use. */
+  | {
+      action: 'helloRequest';
+      payload: HelloRequestPayload;
+    }
+  | {
+      action: 'capabilitiesRequest';
+      payload: CapabilitiesRequestPayload;
+    }
+  | {
+      action: 'saveBeginRequest';
+      payload: SaveBeginRequestPayload;
+    }
+  | {
+      action: 'saveChunkRequest';
+      payload: SaveChunkRequestPayload;
+    }
+  /** Answered by a `saveResponse`. */
+  | {
+      action: 'saveCommitRequest';
+      payload: SaveCommitRequestPayload;
+    }
+  /**
+   * Stops a search that is running or waiting to run. It is not held up by the search, even
+   * if they have the same correlation id.
+   */
+  | {
+      action: 'cancelRequest';
+      payload: CancelRequestPayload;
+    }
+);
+
+export type Response = {
+  correlationId: CorrelationId;
+  version: MessageVersion;
+} & (
+  | {
+      action: 'saveResponse';
+      payload: SaveResponsePayload;
+    }
+  | {
+      action: 'removeResponse';
+      payload: RemoveResponsePayload;
+    }
+  | {
+      action: 'searchResponseHeader';
+      payload: SearchResponseHeaderPayload;
+    }
+  | {
+      action: 'searchResponseSite';
+      payload: SearchResponseSitePayload;
+    }
+  | {
+      action: 'addAnnotationResponse';
+      payload: AddAnnotationResponsePayload;
+    }
+  | {
+      action: 'editAnnotationResponse';
+      payload: EditAnnotationResponsePayload;
+    }
+  | {
+      action: 'removeAnnotationResponse';
+      payload: RemoveAnnotationResponsePayload;
+    }
+  | {
+      action: 'listAnnotationsResponse';
+      payload: ListAnnotationsResponsePayload;
+    }
+  | {
+      action: 'setStatusResponse';
+      payload: SetStatusResponsePayload;
+    }
+  | {
+      action: 'listResponseHeader';
+      payload: ListResponseHeaderPayload;
+    }
+  | {
+      action: 'listResponseSite';
+      payload: ListResponseSitePayload;
+    }
+  | {
+      action: 'restoreResponse';
+      payload: RestoreResponsePayload;
+    }
+  | {
+      action: 'emptyTrashResponse';
+      payload: EmptyTrashResponsePayload;
+    }
+  | {
+      action: 'statsResponse';
+      payload: StatsResponsePayload;
+    }
+  | {
+      action: 'backupResponse';
+      payload: BackupResponsePayload;
+    }
+  | {
+      action: 'maintenanceResponse';
+      payload: MaintenanceResponsePayload;
+    }
+  | {
+      action: 'configResponse';
+      payload: Settings;
+    }
+  | {
+      action: 'subscribeResponse';
+      payload: SubscribeResponsePayload;
+    }
+  | {
+      action: 'getResponse';
+      payload: GetResponsePayload;
+    }
+  | {
+      action: 'helloResponse';
+      payload: HelloResponsePayload;
+    }
+  | {
+      action: 'capabilitiesResponse';
+      payload: CapabilitiesResponsePayload;
+    }
+  | {
+      action: 'errorResponse';
+      payload: ErrorResponsePayload;
+    }
+  | {
+      action: 'responseChunk';
+      payload: ResponseChunkPayload;
+    }
+  | {
+      action: 'saveBeginResponse';
+      payload: SaveBeginResponsePayload;
+    }
+  | {
+      action: 'saveChunkResponse';
+      payload: SaveChunkResponsePayload;
+    }
+  | {
+      action: 'cancelResponse';
+      payload: CancelResponsePayload;
+    }
+  /** Sent without a request to subscribers, after a site is changed by any host process. */
+  | {
+      action: 'siteChanged';
+      payload: SiteChangedPayload;
+    }
+);
+
+/**
+ * One part of a response too large to send in one message.
+ *
+ * The `data` of the `count` chunks, in order of `index`, joins to give the JSON of the response.
+ */
+export type ResponseChunkPayload = {
+  count: number;
+  data: string;
+  index: number;
+};
+
+export type RestoreRequestPayload = {
+  url: Url;
+};
+
+export type RestoreResponsePayload = Record<string, never>;
+
+/**
+ * Starts a save of a site whose text is too large for one message.
+ *
+ * The text is sent in `saveChunkRequest`s, and the site is saved by a `saveCommitRequest`, which
+ * only the client that started the save may send.
+ */
+export type SaveBeginRequestPayload = {
+  title: Title;
+  url: Url;
+};
+
+export type SaveBeginResponsePayload = {
+  uploadId: UploadId;
+};
+
+/** `index` counts from 0. A chunk sent again replaces the one with the same index. */
+export type SaveChunkRequestPayload = {
+  index: number;
+  text: string;
+  uploadId: UploadId;
+};
+
+export type SaveChunkResponsePayload = Record<string, never>;
+
+/** Saves the site with the text of chunks `0` to `count - 1`, joined in order. */
+export type SaveCommitRequestPayload = {
+  count: number;
+  uploadId: UploadId;
+};
+
+export type SaveRequestPayload = {
+  innerText: InnerText;
+  title: Title;
+  url: Url;
+};
+
+export type SaveResponsePayload = Record<string, never>;
+
+export type SearchRequestPayload = {
+  pageLength: number;
+  pageNum: number;
+  query: Query;
+  status?: Status | null;
+};
+
+export type SearchResponseHeaderPayload = {
+  hasMore: boolean;
+  pageLength: number;
+  pageNum: number;
+  query: Query;
+};
+
+export type SearchResponseSitePayload = {
+  passage?: PassageRange | null;
+  snippet: Snippet;
+  status: Status;
+  title: Title;
+  url: Url;
+};
+
+export type SemVer = string;
+
+export type SetStatusRequestPayload = {
+  status: Status;
+  url: Url;
+};
+
+export type SetStatusResponsePayload = Record<string, never>;
+
+/** The effective configuration. */
+export type Settings = {
+  /** The config file that was read, if there was one. */
+  configFile?: string | null;
+  /** The path of the database, or `None` if it is in memory. */
+  db?: string | null;
+  /** A file containing the passphrase of an encrypted database. */
+  keyFile?: string | null;
+  maxPageLength: number;
+  /** The largest request, in bytes, that is read. */
+  maxRequestSize: number;
+  profile?: string | null;
+  queryRegex: string;
+  snippetTokens: number;
+  trashRetentionDays: number;
+};
+
+/** A saved site, with its text. */
+export type Site = {
+  createdAt: Timestamp;
+  innerText: InnerText;
+  status: Status;
+  statusUpdatedAt?: Timestamp | null;
+  title: Title;
+  updatedAt: Timestamp;
+  url: Url;
+};
+
+export type SiteChangedPayload = {
+  change: Change;
+  url: Url;
+};
+
+/** Text of a site around the matches of a search, which are wrapped in `<b>` tags. */
+export type Snippet = string;
+
+export type StatsRequestPayload = Record<string, never>;
+
+/** Sizes are in bytes. */
+export type StatsResponsePayload = {
+  databaseSize: number;
+  ftsIndexSize: number;
+  newestCreatedAt?: Timestamp | null;
+  oldestCreatedAt?: Timestamp | null;
+  schemaVersion: SemVer;
+  statusCounts: StatusCounts;
+  topDomains: DomainCount[];
+  totalSites: number;
+  totalTextBytes: number;
+  trashedSites: number;
+};
+
+/** Where a saved site is in the user's backlog. */
+export type Status = 'unread' | 'read' | 'archived';
+
+/** The number of saved sites in each [`Status`], excluding those in the trash. */
+export type StatusCounts = {
+  archived: number;
+  read: number;
+  unread: number;
+};
+
+export type SubscribeRequestPayload = Record<string, never>;
+
+export type SubscribeResponsePayload = Record<string, never>;
+
+export type Timestamp = string;
+
+export type Title = string;
+
+/** Identifies a save whose text is sent in chunks. */
+export type UploadId = number;
+
+export type Url = string;
+
+/** An inclusive range of message versions. */
+export type VersionRange = {
+  max: MessageVersion;
+  min: MessageVersion;
+};
@@ -1,3 +1,7 @@
+import type { Response } from './messages.js';
+
+export * from './messages.js';
+
 export type UUID = `${string}-${string}-${string}-${string}-${string}`;
 
 export type Responder = (response?: any) => void;
@@ -7,49 +11,6 @@ export type State = {
   tab: chrome.tabs.Tab;
 };
 
-export type SaveResponse = {
-  version: string;
-  action: 'saveResponse';
-  payload: null;
-  correlationId: UUID;
-};
-
-export type RemoveResponse = {
-  version: string;
-  action: 'removeResponse';
-  payload: null;
-  correlationId: UUID;
-};
-
-export type SearchResponseHeaderPayload = {
-  query: string;
-  pageNum: number;
-  pageLength: number;
-  hasMore: boolean;
-};
-
-export type SearchResponseHeader = {
-  version: string;
-  action: 'searchResponseHeader';
-  payload: SearchResponseHeaderPayload;
-  correlationId: UUID;
-};
-
-export type SearchResponseSitePayload = {
-  url: string;
-  title: string;
-  snippet: string;
-};
-
-export type SearchResponseSite = {
-  version: string;
-  action: 'searchResponseSite';
-  payload: SearchResponseSitePayload;
-  correlationId: UUID;
-};
-
-export type Response = SaveResponse | RemoveResponse | SearchResponseHeader | SearchResponseSite;
-
 export type Responses = {
   inner: Response[];
 };
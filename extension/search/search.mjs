@@ -30,6 +30,30 @@ const handleSearchResponse = (responses) => {
   }
 };
 
+/**
+ * The correlation id of the search that has yet to respond, if any.
+ *
+ * @type {import('../common/types.js').CorrelationId | null}
+ */
+let pendingSearch = null;
+
+/**
+ * Asks the host to stop the search that has yet to respond, as its results will not be shown.
+ *
+ * @returns {void}
+ */
+const cancelPendingSearch = () => {
+  if (pendingSearch === null) {
+    return;
+  }
+  chrome.runtime.sendMessage({
+    version: SCHEMA_VERSION,
+    action: 'cancelRequest',
+    payload: { correlationId: pendingSearch },
+  });
+  pendingSearch = null;
+};
+
 /**
  * @param {string} value
  * @returns {void}
@@ -38,13 +62,22 @@ const search = (value) => {
   if (value.length === 0) {
     return;
   }
+  cancelPendingSearch();
+  const correlationId = crypto.randomUUID();
+  pendingSearch = correlationId;
   chrome.runtime
     .sendMessage({
       version: SCHEMA_VERSION,
       action: 'searchRequest',
       payload: { query: value, pageNum: 0, pageLength: 100 },
+      correlationId,
     })
     .then((response) => {
+      // A search that was cancelled is answered with the cancelResponse.
+      if (pendingSearch !== correlationId || response.action === 'cancelResponse') {
+        return;
+      }
+      pendingSearch = null;
       handleSearchResponse(response);
       console.debug('response', response);
     });
@@ -59,6 +92,7 @@ const main = () => {
     throw new Error('No search input found');
   }
   searchInput.value = query;
+  window.addEventListener('pagehide', cancelPendingSearch);
   if (query === null) return;
   search(query);
 };
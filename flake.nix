@@ -25,17 +25,28 @@
           installFlags = [ "DESTDIR=${placeholder "out"}" ];
         };
       mkNoematic =
-        pkgs:
+        pkgs: buildFeatures:
         pkgs.rustPlatform.buildRustPackage {
-          inherit pname version src;
+          inherit
+            pname
+            version
+            src
+            buildFeatures
+            ;
 
           cargoLock = {
             lockFile = ./Cargo.lock;
           };
+
+          # SQLCipher is bundled, but uses the system's OpenSSL.
+          buildInputs = pkgs.lib.optionals (builtins.elem "noematic/encryption" buildFeatures) [
+            pkgs.openssl
+          ];
         };
       overlay = final: prev: {
-        noematic = mkNoematic final;
-        noematic-static = mkNoematic final.pkgsStatic;
+        noematic = mkNoematic final [ ];
+        noematic-encryption = mkNoematic final [ "noematic/encryption" ];
+        noematic-static = mkNoematic final.pkgsStatic [ ];
         noematic-extension = mkExt final;
       };
     in
@@ -50,6 +61,7 @@
       {
         packages = {
           noematic = pkgs.noematic;
+          noematic-encryption = pkgs.noematic-encryption;
           noematic-static = pkgs.noematic-static;
           noematic-extension = pkgs.noematic-extension;
           all = pkgs.symlinkJoin {
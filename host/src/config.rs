@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Error};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub use noematic_common::is_valid_profile_name;
+
+const MSG_UNKNOWN_PROFILE: &str = "Unknown profile";
+const MSG_INVALID_PROFILE_NAME: &str = "Invalid profile name";
+const MSG_INVALID_QUERY_REGEX: &str = "Invalid query_regex";
+const MSG_EMPTY_QUERY_REGEX: &str = "query_regex must not match the empty string";
+const MSG_INVALID_SNIPPET_TOKENS: &str = "snippet_tokens must be between 1 and 64";
+const MSG_INVALID_MAX_PAGE_LENGTH: &str = "max_page_length must be at least 1";
+const MSG_INVALID_MAX_REQUEST_SIZE: &str = "max_request_size must be at least 1";
+
+/// The name of the config file within the config directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// The name of the database file within the data directory, or within a profile's directory.
+pub const DB_FILE: &str = "db.sqlite3";
+
+/// Appended to the path of the database to give the path of the socket of the daemon serving it.
+const SOCKET_SUFFIX: &str = ".sock";
+
+/// The directory, relative to the data directory, holding the databases of profiles that do not
+/// specify one.
+const PROFILES_DIR: &str = "profiles";
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+pub const DEFAULT_QUERY_REGEX: &str = r"\W+";
+pub const DEFAULT_SNIPPET_TOKENS: u32 = 40;
+pub const DEFAULT_MAX_PAGE_LENGTH: usize = 100;
+/// The largest message Chromium sends to a native messaging host.
+pub const DEFAULT_MAX_REQUEST_SIZE: u32 = 64 * 1024 * 1024;
+
+/// The largest number of tokens the FTS5 `snippet` function will return.
+const MAX_SNIPPET_TOKENS: u32 = 64;
+
+/// The contents of the config file.
+///
+/// Every setting is optional.
+///
+/// ```toml
+/// # The database, relative to this file. Defaults to db.sqlite3 in the data directory.
+/// db = "/mnt/secure/noematic/db.sqlite3"
+/// # A file containing the passphrase of an encrypted database, relative to this file.
+/// key_file = "/mnt/secure/noematic/key"
+/// # Days to keep removed sites in the trash before purging them.
+/// trash_retention_days = 30
+/// # The largest request, in bytes, that is read. Larger requests are rejected.
+/// max_request_size = 67108864
+///
+/// [search]
+/// # Matches of this pattern are replaced by spaces in queries.
+/// query_regex = '\W+'
+/// # The maximum number of tokens in a snippet, from 1 to 64.
+/// snippet_tokens = 40
+/// # The maximum number of results in a page. Larger requests are rejected.
+/// max_page_length = 100
+///
+/// [profiles.work]
+/// db = "/mnt/secure/noematic/work.sqlite3"
+/// key_file = "/mnt/secure/noematic/work.key"
+///
+/// [profiles.personal]
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub db: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub trash_retention_days: Option<u32>,
+    pub max_request_size: Option<u32>,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SearchConfig {
+    pub query_regex: Option<String>,
+    pub snippet_tokens: Option<u32>,
+    pub max_page_length: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The path of the profile's database, relative to the directory of the config file.
+    ///
+    /// Defaults to `profiles/<name>/db.sqlite3` in the data directory.
+    pub db: Option<PathBuf>,
+    /// Overrides the top-level `key_file`.
+    pub key_file: Option<PathBuf>,
+}
+
+/// Settings given in the environment or on the command line, which take precedence over the
+/// config file.
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub db: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub trash_retention_days: Option<u32>,
+    pub query_regex: Option<String>,
+    pub snippet_tokens: Option<u32>,
+    pub max_page_length: Option<usize>,
+    pub max_request_size: Option<u32>,
+}
+
+/// The effective configuration.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    /// The config file that was read, if there was one.
+    pub config_file: Option<PathBuf>,
+    pub profile: Option<String>,
+    /// The path of the database, or `None` if it is in memory.
+    pub db: Option<PathBuf>,
+    /// A file containing the passphrase of an encrypted database.
+    pub key_file: Option<PathBuf>,
+    pub trash_retention_days: u32,
+    pub query_regex: String,
+    pub snippet_tokens: u32,
+    pub max_page_length: usize,
+    /// The largest request, in bytes, that is read.
+    pub max_request_size: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            config_file: None,
+            profile: None,
+            db: None,
+            key_file: None,
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            query_regex: DEFAULT_QUERY_REGEX.to_string(),
+            snippet_tokens: DEFAULT_SNIPPET_TOKENS,
+            max_page_length: DEFAULT_MAX_PAGE_LENGTH,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`, which need not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is invalid.
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err).context(path.display().to_string()),
+        };
+        let config: Config = toml::from_str(&contents).context(path.display().to_string())?;
+        if let Some(name) = config
+            .profiles
+            .keys()
+            .find(|name| !is_valid_profile_name(name))
+        {
+            return Err(Error::msg(format!("{MSG_INVALID_PROFILE_NAME}: {name}")))
+                .context(path.display().to_string());
+        }
+        Ok(config)
+    }
+
+    /// Returns the path of the database for the profile `name`.
+    ///
+    /// Relative paths are resolved against `config_dir`, and profiles without a database are
+    /// given one in `data_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no such profile.
+    pub fn db_path(
+        &self,
+        name: &str,
+        config_dir: &Path,
+        data_dir: &Path,
+    ) -> Result<PathBuf, Error> {
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| Error::msg(format!("{MSG_UNKNOWN_PROFILE}: {name}")))?;
+        let ret = match &profile.db {
+            Some(db) => config_dir.join(db),
+            None => data_dir.join(PROFILES_DIR).join(name).join(DB_FILE),
+        };
+        Ok(ret)
+    }
+}
+
+impl Settings {
+    /// Combines the defaults, the config file at `config_path` and `overrides`, in increasing
+    /// order of precedence.
+    ///
+    /// The database is given by `overrides.db`, then by the profile, then by the config file, and
+    /// is otherwise `db.sqlite3` in `data_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config file cannot be read, or the resulting settings are invalid.
+    pub fn resolve(
+        config_path: &Path,
+        overrides: Overrides,
+        data_dir: &Path,
+    ) -> Result<Settings, Error> {
+        let mut overrides = overrides;
+        let config = Config::load(config_path)?;
+        let config_dir = config_path.parent().unwrap_or(Path::new(""));
+        let db = if let Some(db) = overrides.db.take() {
+            db
+        } else if let Some(profile) = &overrides.profile {
+            config.db_path(profile, config_dir, data_dir)?
+        } else if let Some(db) = &config.db {
+            config_dir.join(db)
+        } else {
+            data_dir.join(DB_FILE)
+        };
+        Settings::combine(config, Some(config_path), overrides, Some(db))
+    }
+
+    /// Combines the defaults, the config file at `config_path` if one is given, and `overrides`,
+    /// for an in-memory database.
+    ///
+    /// Unlike [`Settings::resolve`], no config file is read unless it is given, so that tests do
+    /// not depend on the user's.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config file cannot be read, or the resulting settings are invalid.
+    pub fn in_memory(config_path: Option<&Path>, overrides: Overrides) -> Result<Settings, Error> {
+        let config = match config_path {
+            Some(config_path) => Config::load(config_path)?,
+            None => Config::default(),
+        };
+        Settings::combine(config, config_path, overrides, None)
+    }
+
+    fn combine(
+        config: Config,
+        config_path: Option<&Path>,
+        overrides: Overrides,
+        db: Option<PathBuf>,
+    ) -> Result<Settings, Error> {
+        let config_dir = config_path.and_then(Path::parent).unwrap_or(Path::new(""));
+        let profile = overrides
+            .profile
+            .as_ref()
+            .and_then(|name| config.profiles.get(name));
+        let defaults = Settings::default();
+        let ret = Settings {
+            config_file: config_path
+                .filter(|config_path| config_path.exists())
+                .map(Path::to_path_buf),
+            profile: overrides.profile,
+            db,
+            key_file: overrides.key_file.or_else(|| {
+                profile
+                    .and_then(|profile| profile.key_file.as_ref())
+                    .or(config.key_file.as_ref())
+                    .map(|key_file| config_dir.join(key_file))
+            }),
+            trash_retention_days: overrides
+                .trash_retention_days
+                .or(config.trash_retention_days)
+                .unwrap_or(defaults.trash_retention_days),
+            query_regex: overrides
+                .query_regex
+                .or(config.search.query_regex)
+                .unwrap_or(defaults.query_regex),
+            snippet_tokens: overrides
+                .snippet_tokens
+                .or(config.search.snippet_tokens)
+                .unwrap_or(defaults.snippet_tokens),
+            max_page_length: overrides
+                .max_page_length
+                .or(config.search.max_page_length)
+                .unwrap_or(defaults.max_page_length),
+            max_request_size: overrides
+                .max_request_size
+                .or(config.max_request_size)
+                .unwrap_or(defaults.max_request_size),
+        };
+        ret.validate()?;
+        Ok(ret)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error describing the first invalid setting.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.query_regex()?;
+        if !(1..=MAX_SNIPPET_TOKENS).contains(&self.snippet_tokens) {
+            return Err(Error::msg(MSG_INVALID_SNIPPET_TOKENS));
+        }
+        if self.max_page_length == 0 {
+            return Err(Error::msg(MSG_INVALID_MAX_PAGE_LENGTH));
+        }
+        if self.max_request_size == 0 {
+            return Err(Error::msg(MSG_INVALID_MAX_REQUEST_SIZE));
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the socket of the daemon serving the database, or `None` if it is in
+    /// memory.
+    #[must_use]
+    pub fn socket(&self) -> Option<PathBuf> {
+        let mut ret = self.db.clone()?.into_os_string();
+        ret.push(SOCKET_SUFFIX);
+        Some(PathBuf::from(ret))
+    }
+
+    /// Reads the passphrase from `key_file`, ignoring a trailing newline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not valid UTF-8.
+    pub fn key(&self) -> Result<Option<String>, Error> {
+        let Some(key_file) = &self.key_file else {
+            return Ok(None);
+        };
+        let key = fs::read_to_string(key_file).context(key_file.display().to_string())?;
+        let key = key.strip_suffix('\n').unwrap_or(&key);
+        let key = key.strip_suffix('\r').unwrap_or(key);
+        Ok(Some(key.to_string()))
+    }
+
+    /// Compiles `query_regex`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it is not a valid regex, or if it matches the empty string.
+    pub fn query_regex(&self) -> Result<Regex, Error> {
+        let ret = Regex::new(&self.query_regex).context(MSG_INVALID_QUERY_REGEX)?;
+        if ret.is_match("") {
+            return Err(Error::msg(MSG_EMPTY_QUERY_REGEX));
+        }
+        Ok(ret)
+    }
+}
@@ -11,10 +11,17 @@ CREATE TABLE IF NOT EXISTS sites (
     title TEXT NOT NULL,
     inner_text TEXT NOT NULL,
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    status TEXT NOT NULL DEFAULT 'unread' CHECK (status IN ('unread', 'read', 'archived')),
+    status_updated_at DATETIME,
+    trashed_at DATETIME
 );
 
-CREATE VIRTUAL TABLE IF NOT EXISTS sites_fts USING fts5(url, title, inner_text);
+CREATE INDEX IF NOT EXISTS sites_status ON sites (status);
+
+CREATE INDEX IF NOT EXISTS sites_trashed_at ON sites (trashed_at);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS sites_fts USING fts5(url, title, inner_text, annotations);
 
 CREATE TRIGGER sites_ai AFTER INSERT ON sites
     BEGIN
@@ -22,7 +29,7 @@ CREATE TRIGGER sites_ai AFTER INSERT ON sites
         VALUES (new.id, new.url, new.title, new.inner_text);
     END;
 
-CREATE TRIGGER sites_au AFTER UPDATE ON sites
+CREATE TRIGGER sites_au AFTER UPDATE OF url, title, inner_text ON sites
     BEGIN
         UPDATE sites_fts
            SET url = new.url,
@@ -36,3 +43,133 @@ CREATE TRIGGER sites_ad AFTER DELETE ON sites
         DELETE FROM sites_fts
          WHERE rowid = old.id;
     END;
+
+CREATE TABLE IF NOT EXISTS passages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    site_id INTEGER NOT NULL REFERENCES sites (id),
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    text TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS passages_site_id ON passages (site_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS passages_fts USING fts5(text, content = 'passages', content_rowid = 'id');
+
+CREATE TRIGGER passages_ai AFTER INSERT ON passages
+    BEGIN
+        INSERT INTO passages_fts (rowid, text)
+        VALUES (new.id, new.text);
+    END;
+
+CREATE TRIGGER passages_ad AFTER DELETE ON passages
+    BEGIN
+        INSERT INTO passages_fts (passages_fts, rowid, text)
+        VALUES ('delete', old.id, old.text);
+    END;
+
+CREATE TRIGGER sites_ad_passages AFTER DELETE ON sites
+    BEGIN
+        DELETE FROM passages
+         WHERE site_id = old.id;
+    END;
+
+CREATE TABLE IF NOT EXISTS annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    site_id INTEGER NOT NULL REFERENCES sites (id),
+    note TEXT NOT NULL,
+    quote TEXT,
+    quote_start INTEGER,
+    quote_end INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS annotations_site_id ON annotations (site_id);
+
+CREATE VIEW IF NOT EXISTS annotations_text AS
+    SELECT site_id, group_concat(note || ' ' || COALESCE(quote, ''), ' ') AS text
+      FROM annotations
+     GROUP BY site_id;
+
+CREATE TRIGGER annotations_ai AFTER INSERT ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = new.site_id)
+         WHERE rowid = new.site_id;
+    END;
+
+CREATE TRIGGER annotations_au AFTER UPDATE ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = new.site_id)
+         WHERE rowid = new.site_id;
+    END;
+
+CREATE TRIGGER annotations_ad AFTER DELETE ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = old.site_id)
+         WHERE rowid = old.site_id;
+    END;
+
+CREATE TRIGGER sites_ad_annotations AFTER DELETE ON sites
+    BEGIN
+        DELETE FROM annotations
+         WHERE site_id = old.id;
+    END;
+
+CREATE TABLE IF NOT EXISTS changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    change TEXT NOT NULL,
+    changed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS changes_changed_at ON changes (changed_at);
+
+CREATE TRIGGER changes_sites_ai AFTER INSERT ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'saved');
+    END;
+
+CREATE TRIGGER changes_sites_au AFTER UPDATE OF url, title, inner_text ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'saved');
+    END;
+
+CREATE TRIGGER changes_sites_au_status AFTER UPDATE OF status ON sites
+    WHEN old.status IS NOT new.status
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'statusChanged');
+    END;
+
+CREATE TRIGGER changes_sites_au_trashed AFTER UPDATE OF trashed_at ON sites
+    WHEN (old.trashed_at IS NULL) != (new.trashed_at IS NULL)
+    BEGIN
+        INSERT INTO changes (url, change)
+        VALUES (new.url, CASE WHEN new.trashed_at IS NULL THEN 'restored' ELSE 'removed' END);
+    END;
+
+CREATE TRIGGER changes_sites_ad AFTER DELETE ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (old.url, 'deleted');
+    END;
+
+CREATE TRIGGER changes_annotations_ai AFTER INSERT ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = new.site_id;
+    END;
+
+CREATE TRIGGER changes_annotations_au AFTER UPDATE ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = new.site_id;
+    END;
+
+CREATE TRIGGER changes_annotations_ad AFTER DELETE ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = old.site_id;
+    END;
@@ -0,0 +1,478 @@
+//! Serving a database to several clients over a Unix socket.
+//!
+//! A daemon owns the database, and speaks the native messaging protocol with each client that
+//! connects to its socket. When the host is started by a browser, it forwards messages to the
+//! daemon, starting one if none is running.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        net::{UnixListener, UnixStream},
+        process::CommandExt,
+    },
+    path::Path,
+    process::{Command, Stdio},
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError, Sender, SyncSender, TrySendError},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use noematic::{
+    Context, Session,
+    config::Settings,
+    encoding::Encoding,
+    message::{CorrelationId, Response},
+};
+
+use crate::{
+    Decoded, Inbound, POLL_INTERVAL, decode_message,
+    pool::{Callback, Cancel, POOL_SIZE, SearchPool},
+    read_messages, write_responses,
+};
+
+const MSG_ALREADY_SERVING: &str = "A daemon is already serving the database";
+const MSG_DAEMON_RUNNING: &str = "Cannot run this command while a daemon is serving the database";
+const MSG_DAEMON_UNAVAILABLE: &str = "Timed out waiting for the daemon to start";
+const MSG_CLIENT_NOT_READING: &str = "Client is not reading its responses";
+const MSG_CLIENT_CLOSED: &str = "Client connection is closed";
+
+/// How long to wait for a newly started daemon to accept connections.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+const START_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+const PIPE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// The number of batches of responses that may wait to be written to a client, beyond which
+/// it is disconnected for not reading them.
+const CLIENT_QUEUE_LENGTH: usize = 64;
+
+type ClientId = u64;
+
+enum Event {
+    Connected(ClientId, ClientWriter),
+    Message(ClientId, Result<Inbound, Error>),
+    /// The responses to a search handled by the [`SearchPool`].
+    Searched(ClientId, CorrelationId, Result<Vec<Response>, Error>),
+    Disconnected(ClientId),
+}
+
+/// Writes the responses to a client on a thread of its own, so that a client that is slow to
+/// read them does not hold up the others.
+struct ClientWriter {
+    sender: SyncSender<Vec<Response>>,
+    stream: UnixStream,
+}
+
+impl ClientWriter {
+    /// Starts writing to `stream`. The thread holds a clone of `writing` until it has written
+    /// every response sent to it.
+    fn spawn(stream: UnixStream, writing: &Arc<()>) -> io::Result<ClientWriter> {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<Response>>(CLIENT_QUEUE_LENGTH);
+        let mut writer = BufWriter::new(stream.try_clone()?);
+        let writing = Arc::clone(writing);
+        thread::spawn(move || {
+            for responses in receiver {
+                if write_responses(&mut writer, Encoding::Json, &responses).is_err() {
+                    // Ends the client's reader thread as well.
+                    let _ = writer.get_ref().shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+            drop(writer);
+            drop(writing);
+        });
+        Ok(ClientWriter { sender, stream })
+    }
+
+    /// Queues `responses` to be written, failing if the client has too many waiting already.
+    fn write(&self, responses: Vec<Response>) -> Result<(), Error> {
+        if responses.is_empty() {
+            return Ok(());
+        }
+        match self.sender.try_send(responses) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Error::msg(MSG_CLIENT_NOT_READING)),
+            Err(TrySendError::Disconnected(_)) => Err(Error::msg(MSG_CLIENT_CLOSED)),
+        }
+    }
+}
+
+struct Client {
+    session: Session,
+    writer: ClientWriter,
+    /// Messages waiting for the search with the same correlation id to finish, in the order they
+    /// were received.
+    pending: VecDeque<Decoded>,
+    /// The correlation ids of the client's searches that are running.
+    searching: HashMap<CorrelationId, Cancel>,
+    /// Whether the client has stopped sending messages, so that it is removed once its searches
+    /// have finished.
+    disconnected: bool,
+}
+
+impl Client {
+    /// Handles a message once the client's pending messages have been handled, except for a
+    /// `cancelRequest`, which is answered at once.
+    fn receive(
+        &mut self,
+        decoded: Decoded,
+        id: ClientId,
+        context: &mut Context,
+        pool: Option<&SearchPool>,
+        sender: &Sender<Event>,
+    ) -> Result<(), Error> {
+        if let Decoded::Request(request) = &decoded
+            && let Some(response) = noematic::cancel(request, |correlation_id| {
+                self.searching
+                    .get(correlation_id)
+                    .is_some_and(Cancel::cancel)
+            })
+        {
+            return self.writer.write(vec![response]);
+        }
+        self.pending.push_back(decoded);
+        self.handle_pending(id, context, pool, sender)
+    }
+
+    /// Handles the client's pending messages until one has the correlation id of a running
+    /// search, so that the responses to requests with the same correlation id are written in the
+    /// order of the requests.
+    ///
+    /// Searches are sent to `pool`, if there is one, which reports their responses to `sender`.
+    fn handle_pending(
+        &mut self,
+        id: ClientId,
+        context: &mut Context,
+        pool: Option<&SearchPool>,
+        sender: &Sender<Event>,
+    ) -> Result<(), Error> {
+        while let Some(decoded) = self.pending.pop_front() {
+            if self.searching.contains_key(decoded.correlation_id()) {
+                self.pending.push_front(decoded);
+                break;
+            }
+            let responses = match decoded {
+                Decoded::Rejected(response) => vec![response],
+                Decoded::Request(request) => match pool {
+                    Some(pool) if SearchPool::accepts(&request) => {
+                        let correlation_id = request.correlation_id.clone();
+                        let key = correlation_id.clone();
+                        let sender = sender.clone();
+                        let callback: Callback = Box::new(move |result| {
+                            let _ = sender.send(Event::Searched(id, correlation_id, result));
+                        });
+                        let cancel = pool.submit(request, callback)?;
+                        self.searching.insert(key, cancel);
+                        continue;
+                    }
+                    _ => noematic::handle_request(context, &mut self.session, request)?,
+                },
+            };
+            self.writer.write(responses)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the connection after an error, which is reported on stderr.
+    fn close(&self, id: ClientId, err: &Error) {
+        eprintln!("Client {id}: {err:#}");
+        // Ends the client's reader thread as well.
+        let _ = self.writer.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Listens on `socket`, replacing it if it was left behind by a daemon that is no longer running.
+///
+/// # Errors
+///
+/// Returns an error if another daemon is listening on `socket`, or it cannot be bound.
+pub fn bind(socket: &Path) -> Result<UnixListener, Error> {
+    match UnixListener::bind(socket) {
+        Ok(listener) => return Ok(listener),
+        Err(err) if err.kind() == io::ErrorKind::AddrInUse => {}
+        Err(err) => return Err(err.into()),
+    }
+    if UnixStream::connect(socket).is_ok() {
+        return Err(Error::msg(MSG_ALREADY_SERVING));
+    }
+    fs::remove_file(socket)?;
+    Ok(UnixListener::bind(socket)?)
+}
+
+/// # Errors
+///
+/// Returns an error if a daemon is serving the database given by `settings`.
+pub fn ensure_not_running(settings: &Settings) -> Result<(), Error> {
+    let running = settings
+        .socket()
+        .is_some_and(|socket| UnixStream::connect(socket).is_ok());
+    if running {
+        return Err(Error::msg(MSG_DAEMON_RUNNING));
+    }
+    Ok(())
+}
+
+/// Accepts connections on another thread, and reads the messages of each, of at most
+/// `max_length` bytes, and writes its responses, on threads of its own.
+fn spawn_acceptor(
+    listener: UnixListener,
+    sender: Sender<Event>,
+    max_length: u32,
+    writing: Arc<()>,
+) {
+    thread::spawn(move || {
+        for (id, stream) in (0..).zip(listener.incoming()) {
+            let Ok((writer, reader)) = stream.and_then(|stream| {
+                let reader = stream.try_clone()?;
+                Ok((ClientWriter::spawn(stream, &writing)?, reader))
+            }) else {
+                continue;
+            };
+            if sender.send(Event::Connected(id, writer)).is_err() {
+                break;
+            }
+            let sender = sender.clone();
+            thread::spawn(move || {
+                let reader = BufReader::new(reader);
+                let result = read_messages(reader, max_length, |message| {
+                    sender.send(Event::Message(id, Ok(message))).is_ok()
+                });
+                let event = match result {
+                    Ok(()) => Event::Disconnected(id),
+                    Err(err) => Event::Message(id, Err(err)),
+                };
+                let _ = sender.send(event);
+            });
+        }
+    });
+}
+
+/// Sends each client the events returned by `changes`, closing the connections that fail.
+fn notify(
+    context: &Context,
+    clients: &mut HashMap<ClientId, Client>,
+    changes: fn(&Context, &mut Session) -> Result<Vec<Response>, Error>,
+) {
+    clients.retain(|id, client| {
+        let result = changes(context, &mut client.session)
+            .and_then(|responses| client.writer.write(responses));
+        match result {
+            Ok(()) => true,
+            Err(err) => {
+                client.close(*id, &err);
+                false
+            }
+        }
+    });
+}
+
+/// Serves `context` to the clients connecting to `listener`, which is bound to `socket`.
+///
+/// An error in handling a client's messages, or a client falling too far behind in reading its
+/// responses, closes its connection. The daemon exits, removing `socket`, once it has had no
+/// clients, nor responses left to write, for `idle_timeout`.
+///
+/// # Errors
+///
+/// Returns an error if `socket` cannot be removed.
+pub fn serve(
+    context: &mut Context,
+    listener: UnixListener,
+    socket: &Path,
+    idle_timeout: Option<Duration>,
+) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel();
+    // Held by each thread writing to a client, which may outlive the client.
+    let writing = Arc::new(());
+    spawn_acceptor(
+        listener,
+        sender.clone(),
+        context.settings().max_request_size,
+        Arc::clone(&writing),
+    );
+    let pool = SearchPool::new(context, POOL_SIZE)?;
+    let mut clients: HashMap<ClientId, Client> = HashMap::new();
+    let mut idle_since = Some(Instant::now());
+
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Event::Connected(id, writer)) => {
+                let client = Client {
+                    session: Session::new(),
+                    writer,
+                    pending: VecDeque::new(),
+                    searching: HashMap::new(),
+                    disconnected: false,
+                };
+                clients.insert(id, client);
+            }
+            Ok(Event::Message(id, message)) => {
+                let Some(client) = clients.get_mut(&id) else {
+                    continue;
+                };
+                let result = message
+                    .and_then(|message| decode_message(context, Encoding::Json, message))
+                    .and_then(|decoded| {
+                        client.receive(decoded, id, context, pool.as_ref(), &sender)
+                    });
+                if let Err(err) = result {
+                    client.close(id, &err);
+                    clients.remove(&id);
+                }
+                // Changes made by the request are also reported to the other clients.
+                notify(context, &mut clients, Context::collect_changes);
+            }
+            Ok(Event::Searched(id, correlation_id, responses)) => {
+                let Some(client) = clients.get_mut(&id) else {
+                    continue;
+                };
+                client.searching.remove(&correlation_id);
+                let result = responses
+                    .and_then(|responses| client.writer.write(responses))
+                    .and_then(|()| client.handle_pending(id, context, pool.as_ref(), &sender));
+                if let Err(err) = result {
+                    client.close(id, &err);
+                    clients.remove(&id);
+                } else if client.disconnected && client.searching.is_empty() {
+                    clients.remove(&id);
+                }
+                notify(context, &mut clients, Context::collect_changes);
+            }
+            Ok(Event::Disconnected(id)) => {
+                // A client's pending messages wait for its searches, so it has none once they
+                // have finished.
+                match clients.get_mut(&id) {
+                    Some(client) if !client.searching.is_empty() => client.disconnected = true,
+                    _ => {
+                        clients.remove(&id);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                notify(context, &mut clients, Context::poll_changes);
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        // The acceptor holds one reference.
+        if !clients.is_empty() || Arc::strong_count(&writing) > 2 {
+            idle_since = None;
+        } else if let Some(idle_timeout) = idle_timeout {
+            let idle_since = *idle_since.get_or_insert_with(Instant::now);
+            if idle_since.elapsed() >= idle_timeout {
+                break;
+            }
+        }
+    }
+
+    fs::remove_file(socket)?;
+    Ok(())
+}
+
+/// Returns arguments which give a daemon the same settings as this process.
+///
+/// Each value is joined to its flag, so that a value starting with `-` is not taken for a flag.
+fn settings_args(settings: &Settings) -> Vec<OsString> {
+    fn arg(flag: &str, value: impl AsRef<OsStr>) -> OsString {
+        let mut ret = OsString::from(format!("{flag}="));
+        ret.push(value);
+        ret
+    }
+    let mut ret: Vec<OsString> = Vec::new();
+    let paths = [
+        ("--config", &settings.config_file),
+        ("--db", &settings.db),
+        ("--key-file", &settings.key_file),
+    ];
+    for (flag, path) in paths {
+        if let Some(path) = path {
+            ret.push(arg(flag, path));
+        }
+    }
+    if let Some(profile) = &settings.profile {
+        ret.push(arg("--profile", profile));
+    }
+    ret.extend([
+        arg(
+            "--trash-retention-days",
+            settings.trash_retention_days.to_string(),
+        ),
+        arg("--query-regex", &settings.query_regex),
+        arg("--snippet-tokens", settings.snippet_tokens.to_string()),
+        arg("--max-page-length", settings.max_page_length.to_string()),
+        arg("--max-request-size", settings.max_request_size.to_string()),
+    ]);
+    ret
+}
+
+/// Starts a daemon serving the database given by `settings`, and connects to it.
+fn start(settings: &Settings, socket: &Path, idle_timeout: u64) -> Result<UnixStream, Error> {
+    let mut child = Command::new(env::current_exe()?)
+        .args(settings_args(settings))
+        .arg("serve")
+        .arg("--idle-timeout")
+        .arg(idle_timeout.to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keeps the daemon running when the browser stops the host's process group.
+        .process_group(0)
+        .spawn()?;
+    // Reaps the daemon if it exits first, for example because another daemon was started at the
+    // same time.
+    thread::spawn(move || child.wait());
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        match UnixStream::connect(socket) {
+            Ok(stream) => return Ok(stream),
+            Err(_) if Instant::now() < deadline => thread::sleep(START_RETRY_INTERVAL),
+            Err(err) => return Err(Error::new(err).context(MSG_DAEMON_UNAVAILABLE)),
+        }
+    }
+}
+
+/// Forwards messages between stdin and stdout and the daemon listening on `socket`, starting a
+/// daemon with `settings` and `idle_timeout` if none is running.
+///
+/// # Errors
+///
+/// Returns an error if the daemon cannot be started, or a message cannot be forwarded.
+pub fn proxy(settings: &Settings, socket: &Path, idle_timeout: u64) -> Result<(), Error> {
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(_) => start(settings, socket, idle_timeout)?,
+    };
+    let writer = stream.try_clone()?;
+    // Messages are forwarded as they are, so that the daemon can enforce its limits on them.
+    thread::spawn(move || {
+        let _ = pipe(io::stdin(), &writer);
+        // Lets the daemon know that there will be no more requests.
+        let _ = writer.shutdown(Shutdown::Write);
+    });
+    pipe(stream, io::stdout())?;
+    Ok(())
+}
+
+/// Copies bytes from `reader` to `writer` until EOF, flushing as they are written.
+fn pipe(mut reader: impl Read, mut writer: impl Write) -> io::Result<()> {
+    let mut buf = [0; PIPE_BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..n])?;
+        writer.flush()?;
+    }
+}
@@ -1,26 +1,60 @@
+pub mod backup;
+pub mod encryption;
+pub mod maintenance;
+mod passage;
 mod schema_version;
+pub mod upload;
 
-use rusqlite::{Connection, Transaction, params};
+use std::{collections::HashMap, path::Path, thread, time::Duration};
+
+use rusqlite::{
+    Connection, ErrorCode, OptionalExtension, Row, Transaction, TransactionBehavior, params,
+};
 
 use self::schema_version::SchemaVersion;
 use crate::message::{
-    Query, RemoveRequestPayload, SaveRequestPayload, SearchRequestPayload,
-    SearchResponseSitePayload,
+    AddAnnotationRequestPayload, Annotation, AnnotationId, DomainCount,
+    EditAnnotationRequestPayload, GetRequestPayload, ListAnnotationsRequestPayload,
+    ListRequestPayload, ListResponseSitePayload, PassageRange, Query, Quote,
+    RemoveAnnotationRequestPayload, RemoveRequestPayload, RestoreRequestPayload,
+    SaveRequestPayload, SearchRequestPayload, SearchResponseSitePayload, SetStatusRequestPayload,
+    Site, SiteChangedPayload, Snippet, StatsResponsePayload, Status, StatusCounts,
 };
 
+/// The tokenizer of the full-text indexes, which is the FTS5 default as `create.sql` does not
+/// give one.
+pub const FTS_TOKENIZER: &str = "unicode61";
+
 const MSG_INVALID_SCHEMA_VERSION: &str = "Invalid schema version";
 
+const MSG_MISSING_SCHEMA_VERSION: &str = "Missing schema version";
+
 const CREATE_SQL: &str = include_str!("create.sql");
 
+const MIGRATE_0_2_0_SQL: &str = include_str!("db/migrations/0.2.0.sql");
+
+const MIGRATE_0_3_0_SQL: &str = include_str!("db/migrations/0.3.0.sql");
+
+const MIGRATE_0_4_0_SQL: &str = include_str!("db/migrations/0.4.0.sql");
+
+const MIGRATE_0_5_0_SQL: &str = include_str!("db/migrations/0.5.0.sql");
+
+const MIGRATE_0_6_0_SQL: &str = include_str!("db/migrations/0.6.0.sql");
+
 #[allow(clippy::const_is_empty)]
 const _: () = assert!(!CREATE_SQL.is_empty());
 
-fn select_version(tx: &Transaction) -> Result<Option<SchemaVersion>, rusqlite::Error> {
-    let mut statement = tx.prepare(
+/// Returns the version of the schema created by this build.
+pub fn current_version() -> semver::Version {
+    SchemaVersion::CURRENT.into_inner()
+}
+
+fn select_version(connection: &Connection) -> Result<Option<SchemaVersion>, rusqlite::Error> {
+    let mut statement = connection.prepare(
         "\
 SELECT major, minor, patch
 FROM schema_version
-ORDER BY applied_at DESC
+ORDER BY applied_at DESC, rowid DESC
 LIMIT 1
 ",
     )?;
@@ -36,26 +70,44 @@ LIMIT 1
     }
 }
 
-fn get_version(tx: &Transaction) -> Result<Option<SchemaVersion>, rusqlite::Error> {
+fn get_version(connection: &Connection) -> Result<Option<SchemaVersion>, rusqlite::Error> {
     let table_exists: bool = {
         let query = "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')";
-        tx.query_row(query, [], |row| row.get(0))?
+        connection.query_row(query, [], |row| row.get(0))?
     };
     if !table_exists {
         return Ok(None);
     }
     let version_exists: bool = {
         let query = "SELECT EXISTS (SELECT 1 FROM schema_version)";
-        tx.query_row(query, [], |row| row.get(0))?
+        connection.query_row(query, [], |row| row.get(0))?
     };
     if version_exists {
-        let maybe_version = select_version(tx)?;
+        let maybe_version = select_version(connection)?;
         return Ok(maybe_version);
     }
     Ok(None)
 }
 
-fn migrate(_tx: &Transaction, _from_version: SchemaVersion, _to_version: SchemaVersion) {}
+fn migrate(tx: &Transaction, from_version: &SchemaVersion) -> Result<(), rusqlite::Error> {
+    if *from_version < SchemaVersion::new(0, 2, 0) {
+        tx.execute_batch(MIGRATE_0_2_0_SQL)?;
+        index_all_passages(tx)?;
+    }
+    if *from_version < SchemaVersion::new(0, 3, 0) {
+        tx.execute_batch(MIGRATE_0_3_0_SQL)?;
+    }
+    if *from_version < SchemaVersion::new(0, 4, 0) {
+        tx.execute_batch(MIGRATE_0_4_0_SQL)?;
+    }
+    if *from_version < SchemaVersion::new(0, 5, 0) {
+        tx.execute_batch(MIGRATE_0_5_0_SQL)?;
+    }
+    if *from_version < SchemaVersion::new(0, 6, 0) {
+        tx.execute_batch(MIGRATE_0_6_0_SQL)?;
+    }
+    Ok(())
+}
 
 fn insert_version(
     tx: &Transaction,
@@ -71,13 +123,79 @@ VALUES (?, ?, ?)
     Ok(version)
 }
 
+/// How long a connection waits for another process to release a lock before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of times [`retry`] attempts an operation.
+const RETRY_ATTEMPTS: u32 = 5;
+
+/// The pause after the first failed attempt, which doubles after each one.
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Prepares a connection to a database file for use by several processes at once.
+///
+/// WAL mode lets readers proceed while another process writes, and the busy timeout makes
+/// writers wait for each other rather than fail.  Transactions take the write lock when they
+/// begin, since a read transaction cannot wait to be upgraded to a write transaction.
+pub fn configure(connection: &mut Connection) -> Result<(), rusqlite::Error> {
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    // Switching a new database to WAL mode fails at once, rather than waiting, while another
+    // process is creating it.
+    retry(|| {
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+    })?;
+    connection.set_transaction_behavior(TransactionBehavior::Immediate);
+    Ok(())
+}
+
+/// Prepares a read-only connection to a database file that has been configured by [`configure`].
+pub fn configure_reader(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.busy_timeout(BUSY_TIMEOUT)
+}
+
+/// Runs `f`, running it again with exponential backoff while the database is locked by another
+/// process for longer than [`BUSY_TIMEOUT`].
+pub fn retry<T>(mut f: impl FnMut() -> Result<T, rusqlite::Error>) -> Result<T, rusqlite::Error> {
+    let mut backoff = RETRY_BACKOFF;
+    for _ in 1..RETRY_ATTEMPTS {
+        match f() {
+            Err(rusqlite::Error::SqliteFailure(err, _))
+                if matches!(
+                    err.code,
+                    ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked
+                ) =>
+            {
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+    f()
+}
+
+/// Backs up the database at `db_path` if [`init_tables`] is about to migrate it.
+pub fn backup_if_migrating(
+    connection: &Connection,
+    db_path: &Path,
+    key: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    match get_version(connection)? {
+        Some(version) if version < SchemaVersion::CURRENT => {
+            backup::backup_before_migration(connection, db_path, &version, key)
+        }
+        _ => Ok(()),
+    }
+}
+
 pub fn init_tables(connection: &mut Connection) -> Result<(), anyhow::Error> {
     let tx = connection.transaction()?;
     let maybe_version = get_version(&tx)?;
     match maybe_version {
         Some(version) if version == SchemaVersion::CURRENT => {}
         Some(version) if version < SchemaVersion::CURRENT => {
-            migrate(&tx, version, SchemaVersion::CURRENT);
+            migrate(&tx, &version)?;
             insert_version(&tx, SchemaVersion::CURRENT)?;
         }
         Some(_) => {
@@ -92,56 +210,203 @@ pub fn init_tables(connection: &mut Connection) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-pub fn upsert_site(
+/// Replaces the passages of the given site with those split from `inner_text`.
+fn index_passages(
     connection: &Connection,
-    save_payload: &SaveRequestPayload,
+    site_id: i64,
+    inner_text: &str,
 ) -> Result<(), rusqlite::Error> {
+    connection.execute("DELETE FROM passages WHERE site_id = ?", params![site_id])?;
     let mut statement = connection.prepare(
         "\
+INSERT INTO passages (site_id, start_offset, end_offset, text)
+VALUES (?, ?, ?, ?)
+",
+    )?;
+    for passage in passage::split(inner_text) {
+        statement.execute(params![site_id, passage.start, passage.end, passage.text])?;
+    }
+    Ok(())
+}
+
+fn index_all_passages(connection: &Connection) -> Result<(), rusqlite::Error> {
+    let mut statement = connection.prepare("SELECT id, inner_text FROM sites")?;
+    let mut rows = statement.query(())?;
+    while let Some(row) = rows.next()? {
+        let site_id: i64 = row.get(0)?;
+        let inner_text: String = row.get(1)?;
+        index_passages(connection, site_id, &inner_text)?;
+    }
+    Ok(())
+}
+
+pub fn upsert_site(
+    connection: &Connection,
+    save_payload: &SaveRequestPayload,
+) -> Result<(), rusqlite::Error> {
+    let tx = connection.unchecked_transaction()?;
+    save_site(&tx, save_payload)?;
+    tx.commit()
+}
+
+/// Inserts or updates a site and its passages, which should be done in a transaction.
+fn save_site(
+    connection: &Connection,
+    save_payload: &SaveRequestPayload,
+) -> Result<(), rusqlite::Error> {
+    let site_id: i64 = {
+        let mut statement = connection.prepare(
+            "\
 INSERT INTO sites (url, title, inner_text)
 VALUES (?, ?, ?)
 ON CONFLICT (url) DO UPDATE SET
     title = excluded.title,
     inner_text = excluded.inner_text,
-    updated_at = CURRENT_TIMESTAMP
+    updated_at = CURRENT_TIMESTAMP,
+    trashed_at = NULL
+RETURNING id
 ",
-    )?;
-    statement.execute(params![
-        save_payload.url,
-        save_payload.title,
-        save_payload.inner_text
-    ])?;
-    Ok(())
+        )?;
+        statement.query_row(
+            params![
+                save_payload.url,
+                save_payload.title,
+                save_payload.inner_text
+            ],
+            |row| row.get(0),
+        )?
+    };
+    index_passages(connection, site_id, save_payload.inner_text.as_str())
 }
 
+/// Moves a site to the trash, where it is excluded from search and list results.
 pub fn remove(
     connection: &Connection,
     payload: &RemoveRequestPayload,
 ) -> Result<(), rusqlite::Error> {
-    let mut statement = connection.prepare("DELETE FROM sites WHERE url = ?")?;
+    let mut statement = connection.prepare(
+        "UPDATE sites SET trashed_at = CURRENT_TIMESTAMP WHERE url = ? AND trashed_at IS NULL",
+    )?;
+    statement.execute(params![payload.url])?;
+    Ok(())
+}
+
+pub fn restore(
+    connection: &Connection,
+    payload: &RestoreRequestPayload,
+) -> Result<(), rusqlite::Error> {
+    let mut statement = connection.prepare("UPDATE sites SET trashed_at = NULL WHERE url = ?")?;
     statement.execute(params![payload.url])?;
     Ok(())
 }
 
+/// Permanently deletes every site in the trash, returning the number deleted.
+pub fn empty_trash(connection: &Connection) -> Result<usize, rusqlite::Error> {
+    connection.execute("DELETE FROM sites WHERE trashed_at IS NOT NULL", ())
+}
+
+/// Permanently deletes sites that were trashed more than `retention_days` ago,
+/// returning the number deleted.
+pub fn purge_trash(connection: &Connection, retention_days: u32) -> Result<usize, rusqlite::Error> {
+    let modifier = format!("-{retention_days} days");
+    connection.execute(
+        "DELETE FROM sites WHERE trashed_at <= datetime('now', ?)",
+        params![modifier],
+    )
+}
+
+/// Returns a number which changes whenever another connection commits to the database.
+pub fn data_version(connection: &Connection) -> Result<i64, rusqlite::Error> {
+    connection.pragma_query_value(None, "data_version", |row| row.get(0))
+}
+
+/// Returns the id of the most recent change, or 0 if there are none.
+pub fn last_change(connection: &Connection) -> Result<i64, rusqlite::Error> {
+    connection.query_row("SELECT COALESCE(MAX(id), 0) FROM changes", (), |row| {
+        row.get(0)
+    })
+}
+
+/// Returns the changes made after the change `id`, with the id of each.
+pub fn changes_since(
+    connection: &Connection,
+    id: i64,
+) -> Result<Vec<(i64, SiteChangedPayload)>, rusqlite::Error> {
+    let mut statement =
+        connection.prepare("SELECT id, url, change FROM changes WHERE id > ? ORDER BY id")?;
+    let rows = statement.query_map(params![id], |row| {
+        let payload = SiteChangedPayload {
+            url: row.get(1)?,
+            change: row.get(2)?,
+        };
+        Ok((row.get(0)?, payload))
+    })?;
+    rows.collect()
+}
+
+/// Deletes changes that are too old to be of interest to any subscriber.
+pub fn prune_changes(connection: &Connection) -> Result<usize, rusqlite::Error> {
+    connection.execute(
+        "DELETE FROM changes WHERE changed_at <= datetime('now', '-1 day')",
+        (),
+    )
+}
+
 pub fn search_sites(
     connection: &Connection,
     search_payload: &SearchRequestPayload,
     process: impl Fn(&Query) -> String,
+    snippet_tokens: u32,
 ) -> Result<(Vec<SearchResponseSitePayload>, bool), rusqlite::Error> {
+    // Sites are matched against `sites_fts`, but their text is ranked by the
+    // best-matching passage so that long documents are not penalized.  The
+    // `inner_text` column of `sites_fts` is therefore given no weight, while
+    // matches in the user's own annotations are weighted above the rest.  Sites
+    // whose terms do not co-occur in any one passage fall back to the
+    // site-level snippet.
     let mut stmt = connection.prepare(
         "\
-SELECT s.url, s.title, snippet(sites_fts, 2, '<b>', '</b>', '...', 40)
-FROM sites_fts
-JOIN sites s ON sites_fts.rowid = s.id
-WHERE sites_fts MATCH ?
-ORDER BY rank
-LIMIT ? OFFSET ?
+WITH site_hits AS MATERIALIZED (
+    SELECT rowid AS site_id,
+           bm25(sites_fts, 1.0, 1.0, 0.0, 2.0) AS rank,
+           snippet(sites_fts, 2, ?6, ?7, '...', ?5) AS snippet
+    FROM sites_fts
+    WHERE sites_fts MATCH ?1
+),
+passage_hits AS MATERIALIZED (
+    SELECT p.site_id, p.start_offset, p.end_offset,
+           bm25(passages_fts) AS rank,
+           snippet(passages_fts, 0, ?6, ?7, '...', ?5) AS snippet
+    FROM passages_fts
+    JOIN passages p ON passages_fts.rowid = p.id
+    WHERE passages_fts MATCH ?1
+),
+best_passages AS (
+    SELECT site_id, MIN(rank) AS rank, start_offset, end_offset, snippet
+    FROM passage_hits
+    GROUP BY site_id
+)
+SELECT s.url, s.title, COALESCE(b.snippet, h.snippet), b.start_offset, b.end_offset, s.status
+FROM site_hits h
+JOIN sites s ON h.site_id = s.id
+LEFT JOIN best_passages b ON h.site_id = b.site_id
+WHERE s.trashed_at IS NULL AND (?4 IS NULL OR s.status = ?4)
+ORDER BY COALESCE(b.rank, 0.0) + h.rank
+LIMIT ?2 OFFSET ?3
 ",
     )?;
     let query_string = process(&search_payload.query);
     let limit = search_payload.page_length + 1; // extra row for has_more
     let offset = search_payload.page_num * search_payload.page_length;
-    let mut rows = stmt.query(params![query_string, limit, offset])?;
+    let mut rows = stmt.query(params![
+        query_string,
+        limit,
+        offset,
+        search_payload.status,
+        snippet_tokens,
+        Snippet::MATCH_START.to_string(),
+        Snippet::MATCH_END.to_string()
+    ])?;
     let mut results = Vec::new();
     let mut count = 0usize;
     let mut has_more = false;
@@ -154,11 +419,310 @@ LIMIT ? OFFSET ?
         let url = row.get(0)?;
         let title = row.get(1)?;
         let snippet = row.get(2)?;
+        let start: Option<usize> = row.get(3)?;
+        let end: Option<usize> = row.get(4)?;
+        let passage = start
+            .zip(end)
+            .map(|(start, end)| PassageRange { start, end });
+        let status = row.get(5)?;
         results.push(SearchResponseSitePayload {
             url,
             title,
             snippet,
+            passage,
+            status,
         });
     }
     Ok((results, has_more))
 }
+
+pub fn set_status(
+    connection: &Connection,
+    payload: &SetStatusRequestPayload,
+) -> Result<(), rusqlite::Error> {
+    connection.execute(
+        "\
+UPDATE sites
+SET status = ?1,
+    status_updated_at = CURRENT_TIMESTAMP
+WHERE url = ?2 AND status != ?1
+",
+        params![payload.status, payload.url],
+    )?;
+    Ok(())
+}
+
+pub fn list_sites(
+    connection: &Connection,
+    list_payload: &ListRequestPayload,
+) -> Result<(Vec<ListResponseSitePayload>, bool), rusqlite::Error> {
+    let mut stmt = connection.prepare(
+        "\
+SELECT url, title, status, created_at, updated_at, status_updated_at
+FROM sites
+WHERE (trashed_at IS NOT NULL) = ?4 AND (?1 IS NULL OR status = ?1)
+ORDER BY created_at DESC, id DESC
+LIMIT ?2 OFFSET ?3
+",
+    )?;
+    let limit = list_payload.page_length + 1; // extra row for has_more
+    let offset = list_payload.page_num * list_payload.page_length;
+    let mut rows = stmt.query(params![
+        list_payload.status,
+        limit,
+        offset,
+        list_payload.trashed
+    ])?;
+    let mut results = Vec::new();
+    let mut has_more = false;
+    while let Some(row) = rows.next()? {
+        if results.len() == list_payload.page_length {
+            has_more = true;
+            break;
+        }
+        results.push(ListResponseSitePayload {
+            url: row.get(0)?,
+            title: row.get(1)?,
+            status: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+            status_updated_at: row.get(5)?,
+        });
+    }
+    Ok((results, has_more))
+}
+
+/// Returns the saved site with the given url, excluding sites in the trash.
+pub fn get_site(
+    connection: &Connection,
+    payload: &GetRequestPayload,
+) -> Result<Option<Site>, rusqlite::Error> {
+    connection
+        .query_row(
+            "\
+SELECT url, title, inner_text, status, created_at, updated_at, status_updated_at
+FROM sites
+WHERE url = ? AND trashed_at IS NULL
+",
+            params![payload.url],
+            |row| {
+                Ok(Site {
+                    url: row.get(0)?,
+                    title: row.get(1)?,
+                    inner_text: row.get(2)?,
+                    status: row.get(3)?,
+                    created_at: row.get(4)?,
+                    updated_at: row.get(5)?,
+                    status_updated_at: row.get(6)?,
+                })
+            },
+        )
+        .optional()
+}
+
+const SELECT_ANNOTATION: &str = "\
+SELECT a.id, s.url, a.note, a.quote, a.quote_start, a.quote_end, a.created_at, a.updated_at
+FROM annotations a
+JOIN sites s ON a.site_id = s.id
+";
+
+fn annotation_from_row(row: &Row) -> Result<Annotation, rusqlite::Error> {
+    let text: Option<String> = row.get(3)?;
+    let start: Option<usize> = row.get(4)?;
+    let end: Option<usize> = row.get(5)?;
+    let quote = match (text, start, end) {
+        (Some(text), Some(start), Some(end)) => Some(Quote {
+            text: text.into(),
+            start,
+            end,
+        }),
+        _ => None,
+    };
+    Ok(Annotation {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        note: row.get(2)?,
+        quote,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn select_annotation(
+    connection: &Connection,
+    id: AnnotationId,
+) -> Result<Option<Annotation>, rusqlite::Error> {
+    let query = format!("{SELECT_ANNOTATION}WHERE a.id = ?");
+    connection
+        .query_row(&query, params![id], annotation_from_row)
+        .optional()
+}
+
+pub fn insert_annotation(
+    connection: &Connection,
+    payload: &AddAnnotationRequestPayload,
+) -> Result<Option<Annotation>, rusqlite::Error> {
+    let quote = payload.quote.as_ref();
+    let id: Option<AnnotationId> = connection
+        .query_row(
+            "\
+INSERT INTO annotations (site_id, note, quote, quote_start, quote_end)
+SELECT id, ?, ?, ?, ?
+FROM sites
+WHERE url = ? AND trashed_at IS NULL
+RETURNING id
+",
+            params![
+                payload.note,
+                quote.map(|q| &q.text),
+                quote.map(|q| q.start),
+                quote.map(|q| q.end),
+                payload.url
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match id {
+        Some(id) => select_annotation(connection, id),
+        None => Ok(None),
+    }
+}
+
+pub fn update_annotation(
+    connection: &Connection,
+    payload: &EditAnnotationRequestPayload,
+) -> Result<Option<Annotation>, rusqlite::Error> {
+    let quote = payload.quote.as_ref();
+    let changed = connection.execute(
+        "\
+UPDATE annotations
+SET note = ?,
+    quote = ?,
+    quote_start = ?,
+    quote_end = ?,
+    updated_at = CURRENT_TIMESTAMP
+WHERE id = ?
+",
+        params![
+            payload.note,
+            quote.map(|q| &q.text),
+            quote.map(|q| q.start),
+            quote.map(|q| q.end),
+            payload.id
+        ],
+    )?;
+    if changed == 0 {
+        return Ok(None);
+    }
+    select_annotation(connection, payload.id)
+}
+
+pub fn delete_annotation(
+    connection: &Connection,
+    payload: &RemoveAnnotationRequestPayload,
+) -> Result<(), rusqlite::Error> {
+    connection.execute("DELETE FROM annotations WHERE id = ?", params![payload.id])?;
+    Ok(())
+}
+
+pub fn list_annotations(
+    connection: &Connection,
+    payload: &ListAnnotationsRequestPayload,
+) -> Result<Vec<Annotation>, rusqlite::Error> {
+    let query = format!(
+        "{SELECT_ANNOTATION}WHERE s.url = ? AND s.trashed_at IS NULL\nORDER BY a.created_at, a.id"
+    );
+    let mut statement = connection.prepare(&query)?;
+    let rows = statement.query_map(params![payload.url], annotation_from_row)?;
+    rows.collect()
+}
+
+/// The number of domains reported by [`stats`].
+const TOP_DOMAINS: usize = 10;
+
+fn top_domains(connection: &Connection) -> Result<Vec<DomainCount>, rusqlite::Error> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut statement = connection.prepare("SELECT url FROM sites WHERE trashed_at IS NULL")?;
+    let mut rows = statement.query(())?;
+    while let Some(row) = rows.next()? {
+        let url: String = row.get(0)?;
+        // The host, without any userinfo or port.
+        let host = url::Url::parse(&url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase));
+        if let Some(host) = host {
+            *counts.entry(host).or_default() += 1;
+        }
+    }
+    let mut ret: Vec<DomainCount> = counts
+        .into_iter()
+        .map(|(domain, count)| DomainCount { domain, count })
+        .collect();
+    ret.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.domain.cmp(&b.domain)));
+    ret.truncate(TOP_DOMAINS);
+    Ok(ret)
+}
+
+pub fn stats(connection: &Connection) -> Result<StatsResponsePayload, anyhow::Error> {
+    let mut status_counts = StatusCounts::default();
+    let mut trashed_sites = 0;
+    {
+        let mut statement = connection.prepare(
+            "\
+SELECT status, trashed_at IS NOT NULL, COUNT(*)
+FROM sites
+GROUP BY 1, 2
+",
+        )?;
+        let mut rows = statement.query(())?;
+        while let Some(row) = rows.next()? {
+            let status: Status = row.get(0)?;
+            let trashed: bool = row.get(1)?;
+            let count: usize = row.get(2)?;
+            match (trashed, status) {
+                (true, _) => trashed_sites += count,
+                (false, Status::Unread) => status_counts.unread += count,
+                (false, Status::Read) => status_counts.read += count,
+                (false, Status::Archived) => status_counts.archived += count,
+            }
+        }
+    }
+    let (total_text_bytes, oldest_created_at, newest_created_at) = connection.query_row(
+        "\
+SELECT COALESCE(SUM(octet_length(inner_text)), 0), MIN(created_at), MAX(created_at)
+FROM sites
+WHERE trashed_at IS NULL
+",
+        (),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let database_size: u64 = connection.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        (),
+        |row| row.get(0),
+    )?;
+    let fts_index_size: u64 = connection.query_row(
+        "\
+SELECT COALESCE(SUM(pgsize), 0)
+FROM dbstat
+WHERE name LIKE 'sites\\_fts\\_%' ESCAPE '\\'
+   OR name LIKE 'passages\\_fts\\_%' ESCAPE '\\'
+",
+        (),
+        |row| row.get(0),
+    )?;
+    let schema_version =
+        get_version(connection)?.ok_or_else(|| anyhow::Error::msg(MSG_MISSING_SCHEMA_VERSION))?;
+    Ok(StatsResponsePayload {
+        total_sites: status_counts.unread + status_counts.read + status_counts.archived,
+        status_counts,
+        trashed_sites,
+        total_text_bytes,
+        database_size,
+        fts_index_size,
+        oldest_created_at,
+        newest_created_at,
+        top_domains: top_domains(connection)?,
+        schema_version: schema_version.into_inner(),
+    })
+}
@@ -0,0 +1,199 @@
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Error;
+use rusqlite::{Connection, OpenFlags, backup::Backup};
+
+use super::{BUSY_TIMEOUT, encryption, schema_version::SchemaVersion};
+
+const MSG_INTEGRITY_CHECK_FAILED: &str = "Integrity check failed";
+const MSG_MISSING_SCHEMA_VERSION: &str = "Missing schema version";
+const MSG_INCOMPATIBLE_SCHEMA_VERSION: &str = "Incompatible schema version";
+const MSG_MISSING_PARENT_DIR: &str = "Missing parent directory";
+const MSG_BACKUP_EXISTS: &str = "Backup file already exists";
+
+/// The directory, relative to the database, holding automatic backups.
+const BACKUPS_DIR: &str = "backups";
+
+/// The number of automatic backups kept before a migration.
+pub const MIGRATION_BACKUPS: usize = 3;
+
+/// The number of automatic backups kept before a restore.
+pub const RESTORE_BACKUPS: usize = 3;
+
+const PAGES_PER_STEP: std::ffi::c_int = 128;
+const PAUSE_BETWEEN_PAGES: Duration = Duration::from_millis(10);
+
+/// Returns the path `path` with `suffix` appended to its file name.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(suffix);
+    path.with_file_name(file_name)
+}
+
+/// Runs `PRAGMA integrity_check`, returning the problems it reports.
+pub fn integrity_check(connection: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut statement = connection.prepare("PRAGMA integrity_check")?;
+    let rows = statement.query_map((), |row| row.get::<_, String>(0))?;
+    let problems: Vec<String> = rows.collect::<Result<_, _>>()?;
+    if problems.len() == 1 && problems[0] == "ok" {
+        return Ok(Vec::new());
+    }
+    Ok(problems)
+}
+
+/// Checks that the database at `path`, encrypted with `key` if it is given, is intact and can be
+/// opened by this build.
+///
+/// Returns the schema version of the database.
+pub fn verify(path: &Path, key: Option<&str>) -> Result<SchemaVersion, Error> {
+    let connection = encryption::open(path, OpenFlags::SQLITE_OPEN_READ_ONLY, key)?;
+    if !integrity_check(&connection)?.is_empty() {
+        return Err(Error::msg(MSG_INTEGRITY_CHECK_FAILED));
+    }
+    let version =
+        super::get_version(&connection)?.ok_or_else(|| Error::msg(MSG_MISSING_SCHEMA_VERSION))?;
+    if version > SchemaVersion::CURRENT {
+        return Err(Error::msg(MSG_INCOMPATIBLE_SCHEMA_VERSION));
+    }
+    Ok(version)
+}
+
+/// Copies the database at `src` into a temporary file beside `dst`, returning its path.
+///
+/// Both are encrypted with `key` if it is given.
+fn copy_to_tmp(src: &Connection, dst: &Path, key: Option<&str>) -> Result<PathBuf, Error> {
+    let tmp = with_suffix(dst, ".tmp");
+    if tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+    let mut connection = encryption::open(&tmp, OpenFlags::default(), key)?;
+    {
+        let backup = Backup::new(src, &mut connection)?;
+        backup.run_to_completion(PAGES_PER_STEP, PAUSE_BETWEEN_PAGES, None)?;
+    }
+    // The copy inherits WAL mode from a live database, but should be a single file.
+    connection.pragma_update_and_check(None, "journal_mode", "DELETE", |row| {
+        row.get::<_, String>(0)
+    })?;
+    Ok(tmp)
+}
+
+/// Copies the database at `src` into a temporary file beside `dst`, verifies the copy, and then
+/// moves it into place.
+///
+/// Both are encrypted with `key` if it is given.
+fn copy(src: &Connection, dst: &Path, key: Option<&str>) -> Result<(), Error> {
+    let tmp = copy_to_tmp(src, dst, key)?;
+    if let Err(err) = verify(&tmp, key) {
+        fs::remove_file(&tmp)?;
+        return Err(err);
+    }
+    fs::rename(&tmp, dst)?;
+    Ok(())
+}
+
+/// Writes a consistent copy of the database to a new file at `path`, even while it is in use.
+pub fn backup(connection: &Connection, path: &Path, key: Option<&str>) -> Result<(), Error> {
+    // The file is created before the copy is moved over it, so that an existing file is never
+    // replaced.
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+    {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(Error::msg(format!(
+                "{MSG_BACKUP_EXISTS}: {}",
+                path.display()
+            )));
+        }
+        result => result?,
+    };
+    let result = copy(connection, path, key);
+    if result.is_err() {
+        fs::remove_file(path)?;
+    }
+    result
+}
+
+/// Replaces the contents of the database at `db_path` with the backup at `src`, first backing up
+/// the database if it exists, keeping only the [`RESTORE_BACKUPS`] most recent such backups.
+///
+/// The backup is copied through a connection to the database rather than over its file, so that
+/// other connections wait for the copy to finish, and then see the restored contents.
+pub fn restore(db_path: &Path, src: &Path, key: Option<&str>) -> Result<(), Error> {
+    verify(src, key)?;
+    let src = encryption::open(src, OpenFlags::SQLITE_OPEN_READ_ONLY, key)?;
+    let exists = db_path.exists();
+    let mut connection = encryption::open(db_path, OpenFlags::default(), key)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    if exists {
+        // A database is often restored because it is damaged, so its backup is not verified.
+        let path = automatic_backup_path(db_path, "pre-restore", "")?;
+        let tmp = copy_to_tmp(&connection, &path, key)?;
+        fs::rename(&tmp, &path)?;
+        prune_automatic_backups(&path, "pre-restore-", RESTORE_BACKUPS)?;
+    }
+    {
+        let backup = Backup::new(&src, &mut connection)?;
+        backup.run_to_completion(PAGES_PER_STEP, PAUSE_BETWEEN_PAGES, None)?;
+    }
+    if !integrity_check(&connection)?.is_empty() {
+        return Err(Error::msg(MSG_INTEGRITY_CHECK_FAILED));
+    }
+    Ok(())
+}
+
+/// Backs up the database at `db_path` before migrating it from `version`, keeping only the
+/// [`MIGRATION_BACKUPS`] most recent such backups.
+pub fn backup_before_migration(
+    connection: &Connection,
+    db_path: &Path,
+    version: &SchemaVersion,
+    key: Option<&str>,
+) -> Result<(), Error> {
+    let path = automatic_backup_path(db_path, "pre-migration", &format!("-{version}"))?;
+    copy(connection, &path, key)?;
+    prune_automatic_backups(&path, "pre-migration-", MIGRATION_BACKUPS)
+}
+
+/// Returns the path of a new automatic backup of the database at `db_path`, in the
+/// [`BACKUPS_DIR`] beside it, creating the directory if necessary.
+///
+/// The file is named by `kind`, the time and `suffix`, so that backups of a kind sort by age.
+fn automatic_backup_path(db_path: &Path, kind: &str, suffix: &str) -> Result<PathBuf, Error> {
+    let dir = db_path
+        .parent()
+        .ok_or_else(|| Error::msg(MSG_MISSING_PARENT_DIR))?
+        .join(BACKUPS_DIR);
+    fs::create_dir_all(&dir)?;
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(dir.join(format!("{kind}-{secs:020}{suffix}.sqlite3")))
+}
+
+/// Removes all but the `keep` most recent backups beside `path` whose names start with `prefix`.
+fn prune_automatic_backups(path: &Path, prefix: &str, keep: usize) -> Result<(), Error> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::msg(MSG_MISSING_PARENT_DIR))?;
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(prefix))
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
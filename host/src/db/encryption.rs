@@ -0,0 +1,97 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::Path,
+};
+
+use anyhow::Error;
+use rusqlite::{Connection, OpenFlags};
+
+use super::{
+    BUSY_TIMEOUT,
+    backup::{verify, with_suffix},
+};
+
+const MSG_UNSUPPORTED: &str = "This build does not support encryption";
+const MSG_WRONG_KEY: &str = "Wrong key, or the database is not encrypted";
+const MSG_INVALID_PATH: &str = "Database path is not valid UTF-8";
+
+fn ensure_supported() -> Result<(), Error> {
+    if cfg!(feature = "encryption") {
+        Ok(())
+    } else {
+        Err(Error::msg(MSG_UNSUPPORTED))
+    }
+}
+
+/// Opens the database at `path`, which is encrypted with `key` if it is given.
+pub fn open(path: &Path, flags: OpenFlags, key: Option<&str>) -> Result<Connection, Error> {
+    let connection = Connection::open_with_flags(path, flags)?;
+    if let Some(key) = key {
+        ensure_supported()?;
+        connection.pragma_update(None, "key", key)?;
+        // SQLCipher does not check the key until the database is read.
+        connection
+            .query_row("SELECT COUNT(*) FROM sqlite_master", (), |_| Ok(()))
+            .map_err(|_| Error::msg(MSG_WRONG_KEY))?;
+    }
+    Ok(connection)
+}
+
+/// Rewrites the database at `db_path`, which is encrypted with `from` if it is given, so that it
+/// is encrypted with `to` if that is given.
+///
+/// The export is written over the database rather than renamed over it, while holding an
+/// exclusive lock, so that connections opened meanwhile wait for it, and then see its contents.
+fn export(db_path: &Path, from: Option<&str>, to: Option<&str>) -> Result<(), Error> {
+    ensure_supported()?;
+    let tmp = with_suffix(db_path, ".tmp");
+    if tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+    // Closing any handle to the database releases the locks of the connection, so this one is
+    // closed after it.
+    let mut file = OpenOptions::new().write(true).open(db_path)?;
+    let connection = open(db_path, OpenFlags::default(), from)?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    // Leaving WAL mode fails while other connections have the database open. In exclusive
+    // locking mode, the lock taken by the first write is then held until the connection closes.
+    connection.pragma_update_and_check(None, "locking_mode", "EXCLUSIVE", |row| {
+        row.get::<_, String>(0)
+    })?;
+    connection.pragma_update_and_check(None, "journal_mode", "DELETE", |row| {
+        row.get::<_, String>(0)
+    })?;
+    connection.execute_batch("BEGIN EXCLUSIVE; COMMIT;")?;
+    {
+        let tmp = tmp.to_str().ok_or_else(|| Error::msg(MSG_INVALID_PATH))?;
+        // An empty key attaches a plaintext database.
+        connection.execute(
+            "ATTACH DATABASE ?1 AS export KEY ?2",
+            (tmp, to.unwrap_or("")),
+        )?;
+        connection.query_row("SELECT sqlcipher_export('export')", (), |_| Ok(()))?;
+        connection.execute("DETACH DATABASE export", ())?;
+    }
+    if let Err(err) = verify(&tmp, to) {
+        fs::remove_file(&tmp)?;
+        return Err(err);
+    }
+    let len = io::copy(&mut File::open(&tmp)?, &mut file)?;
+    file.set_len(len)?;
+    file.sync_all()?;
+    drop(connection);
+    drop(file);
+    fs::remove_file(&tmp)?;
+    Ok(())
+}
+
+/// Encrypts the plaintext database at `db_path` with `key`.
+pub fn encrypt(db_path: &Path, key: &str) -> Result<(), Error> {
+    export(db_path, None, Some(key))
+}
+
+/// Decrypts the database at `db_path`, which is encrypted with `key`.
+pub fn decrypt(db_path: &Path, key: &str) -> Result<(), Error> {
+    export(db_path, Some(key), None)
+}
@@ -0,0 +1,85 @@
+use rusqlite::Connection;
+
+use super::backup::integrity_check;
+use crate::message::{FtsProblem, MaintenanceFindings};
+
+const REBUILD_SQL: &str = include_str!("rebuild.sql");
+
+const REBUILD_PASSAGES_SQL: &str = include_str!("rebuild_passages.sql");
+
+const FTS_TABLES: [&str; 2] = ["sites_fts", "passages_fts"];
+
+/// Runs the FTS5 `integrity-check` command on each full-text index.
+///
+/// A rank of 1 also checks external-content indexes against their content tables.
+fn fts_integrity_check(connection: &Connection) -> Vec<FtsProblem> {
+    let mut ret = Vec::new();
+    for table in FTS_TABLES {
+        let sql = format!("INSERT INTO {table} ({table}, rank) VALUES ('integrity-check', 1)");
+        if let Err(err) = connection.execute(&sql, ()) {
+            ret.push(FtsProblem {
+                table: table.to_string(),
+                message: err.to_string(),
+            });
+        }
+    }
+    ret
+}
+
+fn count(connection: &Connection, sql: &str) -> Result<usize, rusqlite::Error> {
+    connection.query_row(sql, (), |row| row.get(0))
+}
+
+pub fn check(connection: &Connection) -> Result<MaintenanceFindings, rusqlite::Error> {
+    let integrity_check = integrity_check(connection)?;
+    let fts_integrity_check = fts_integrity_check(connection);
+    let sites = count(connection, "SELECT COUNT(*) FROM sites")?;
+    let indexed_sites = count(connection, "SELECT COUNT(*) FROM sites_fts")?;
+    let unindexed_sites = count(
+        connection,
+        "SELECT COUNT(*) FROM sites WHERE id NOT IN (SELECT rowid FROM sites_fts)",
+    )?;
+    let sites_without_passages = count(
+        connection,
+        "\
+SELECT COUNT(*)
+FROM sites
+WHERE inner_text != '' AND id NOT IN (SELECT site_id FROM passages)
+",
+    )?;
+    let healthy = integrity_check.is_empty()
+        && fts_integrity_check.is_empty()
+        && sites == indexed_sites
+        && unindexed_sites == 0
+        && sites_without_passages == 0;
+    Ok(MaintenanceFindings {
+        integrity_check,
+        fts_integrity_check,
+        sites,
+        indexed_sites,
+        unindexed_sites,
+        sites_without_passages,
+        healthy,
+    })
+}
+
+/// Recreates the full-text indexes and passages from the contents of `sites`.
+///
+/// The passage triggers are dropped while the passages are regenerated, since deleting a row that
+/// is missing from an external-content index is reported as corruption.
+pub fn rebuild(connection: &Connection) -> Result<(), rusqlite::Error> {
+    let tx = connection.unchecked_transaction()?;
+    tx.execute_batch(REBUILD_SQL)?;
+    super::index_all_passages(&tx)?;
+    tx.execute_batch(REBUILD_PASSAGES_SQL)?;
+    tx.commit()
+}
+
+/// Merges the segments of each full-text index and then reclaims unused space.
+pub fn optimize(connection: &Connection) -> Result<(), rusqlite::Error> {
+    for table in FTS_TABLES {
+        let sql = format!("INSERT INTO {table} ({table}) VALUES ('optimize')");
+        connection.execute(&sql, ())?;
+    }
+    connection.execute_batch("VACUUM")
+}
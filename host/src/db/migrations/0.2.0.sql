@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS passages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    site_id INTEGER NOT NULL REFERENCES sites (id),
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    text TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS passages_site_id ON passages (site_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS passages_fts USING fts5(text, content = 'passages', content_rowid = 'id');
+
+CREATE TRIGGER passages_ai AFTER INSERT ON passages
+    BEGIN
+        INSERT INTO passages_fts (rowid, text)
+        VALUES (new.id, new.text);
+    END;
+
+CREATE TRIGGER passages_ad AFTER DELETE ON passages
+    BEGIN
+        INSERT INTO passages_fts (passages_fts, rowid, text)
+        VALUES ('delete', old.id, old.text);
+    END;
+
+CREATE TRIGGER sites_ad_passages AFTER DELETE ON sites
+    BEGIN
+        DELETE FROM passages
+         WHERE site_id = old.id;
+    END;
@@ -0,0 +1,52 @@
+DROP TABLE sites_fts;
+
+CREATE VIRTUAL TABLE sites_fts USING fts5(url, title, inner_text, annotations);
+
+INSERT INTO sites_fts (rowid, url, title, inner_text)
+SELECT id, url, title, inner_text
+  FROM sites;
+
+CREATE TABLE IF NOT EXISTS annotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    site_id INTEGER NOT NULL REFERENCES sites (id),
+    note TEXT NOT NULL,
+    quote TEXT,
+    quote_start INTEGER,
+    quote_end INTEGER,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS annotations_site_id ON annotations (site_id);
+
+CREATE VIEW IF NOT EXISTS annotations_text AS
+    SELECT site_id, group_concat(note || ' ' || COALESCE(quote, ''), ' ') AS text
+      FROM annotations
+     GROUP BY site_id;
+
+CREATE TRIGGER annotations_ai AFTER INSERT ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = new.site_id)
+         WHERE rowid = new.site_id;
+    END;
+
+CREATE TRIGGER annotations_au AFTER UPDATE ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = new.site_id)
+         WHERE rowid = new.site_id;
+    END;
+
+CREATE TRIGGER annotations_ad AFTER DELETE ON annotations
+    BEGIN
+        UPDATE sites_fts
+           SET annotations = (SELECT text FROM annotations_text WHERE site_id = old.site_id)
+         WHERE rowid = old.site_id;
+    END;
+
+CREATE TRIGGER sites_ad_annotations AFTER DELETE ON sites
+    BEGIN
+        DELETE FROM annotations
+         WHERE site_id = old.id;
+    END;
@@ -0,0 +1,16 @@
+ALTER TABLE sites ADD COLUMN status TEXT NOT NULL DEFAULT 'unread' CHECK (status IN ('unread', 'read', 'archived'));
+
+ALTER TABLE sites ADD COLUMN status_updated_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS sites_status ON sites (status);
+
+DROP TRIGGER sites_au;
+
+CREATE TRIGGER sites_au AFTER UPDATE OF url, title, inner_text ON sites
+    BEGIN
+        UPDATE sites_fts
+           SET url = new.url,
+               title = new.title,
+               inner_text = new.inner_text
+         WHERE rowid = old.id;
+    END;
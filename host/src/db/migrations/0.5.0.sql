@@ -0,0 +1,3 @@
+ALTER TABLE sites ADD COLUMN trashed_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS sites_trashed_at ON sites (trashed_at);
@@ -0,0 +1,54 @@
+CREATE TABLE IF NOT EXISTS changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    url TEXT NOT NULL,
+    change TEXT NOT NULL,
+    changed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS changes_changed_at ON changes (changed_at);
+
+CREATE TRIGGER changes_sites_ai AFTER INSERT ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'saved');
+    END;
+
+CREATE TRIGGER changes_sites_au AFTER UPDATE OF url, title, inner_text ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'saved');
+    END;
+
+CREATE TRIGGER changes_sites_au_status AFTER UPDATE OF status ON sites
+    WHEN old.status IS NOT new.status
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (new.url, 'statusChanged');
+    END;
+
+CREATE TRIGGER changes_sites_au_trashed AFTER UPDATE OF trashed_at ON sites
+    WHEN (old.trashed_at IS NULL) != (new.trashed_at IS NULL)
+    BEGIN
+        INSERT INTO changes (url, change)
+        VALUES (new.url, CASE WHEN new.trashed_at IS NULL THEN 'restored' ELSE 'removed' END);
+    END;
+
+CREATE TRIGGER changes_sites_ad AFTER DELETE ON sites
+    BEGIN
+        INSERT INTO changes (url, change) VALUES (old.url, 'deleted');
+    END;
+
+CREATE TRIGGER changes_annotations_ai AFTER INSERT ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = new.site_id;
+    END;
+
+CREATE TRIGGER changes_annotations_au AFTER UPDATE ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = new.site_id;
+    END;
+
+CREATE TRIGGER changes_annotations_ad AFTER DELETE ON annotations
+    BEGIN
+        INSERT INTO changes (url, change)
+        SELECT url, 'annotated' FROM sites WHERE id = old.site_id;
+    END;
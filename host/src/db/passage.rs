@@ -0,0 +1,63 @@
+/// A region of a site's text, delimited by offsets in UTF-16 code units, which
+/// is how the extension indexes strings.
+///
+/// Consecutive passages overlap by roughly [`OVERLAP`] characters so that a
+/// phrase straddling a boundary is still contained in at least one passage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Passage<'a> {
+    pub start: usize,
+    pub end: usize,
+    pub text: &'a str,
+}
+
+/// The target length of a passage, in characters.
+pub const LENGTH: usize = 1000;
+
+/// The number of characters shared between consecutive passages.
+pub const OVERLAP: usize = 200;
+
+const _: () = assert!(OVERLAP < LENGTH / 2);
+
+/// Splits `text` into overlapping passages.
+///
+/// Boundaries are moved back to the nearest whitespace where possible so that
+/// words are not cut in half.
+pub fn split(text: &str) -> Vec<Passage<'_>> {
+    let indices: Vec<(usize, char)> = text.char_indices().collect();
+    let len = indices.len();
+    let byte_offset = |i: usize| indices.get(i).map_or(text.len(), |&(b, _)| b);
+    // The UTF-16 offset of each character, and of the end of the text.
+    let utf16_offsets: Vec<usize> = std::iter::once(0)
+        .chain(indices.iter().scan(0, |offset, &(_, c)| {
+            *offset += c.len_utf16();
+            Some(*offset)
+        }))
+        .collect();
+    let is_space = |i: usize| indices[i].1.is_whitespace();
+
+    let mut ret = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let mut end = usize::min(start + LENGTH, len);
+        if end < len {
+            let floor = start + LENGTH / 2;
+            if let Some(i) = (floor..end).rev().find(|&i| is_space(i)) {
+                end = i;
+            }
+        }
+        ret.push(Passage {
+            start: utf16_offsets[start],
+            end: utf16_offsets[end],
+            text: &text[byte_offset(start)..byte_offset(end)],
+        });
+        if end == len {
+            break;
+        }
+        let mut next = end - OVERLAP;
+        if let Some(i) = (next..end).find(|&i| is_space(i)) {
+            next = i + 1;
+        }
+        start = next;
+    }
+    ret
+}
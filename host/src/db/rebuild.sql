@@ -0,0 +1,16 @@
+DROP TABLE IF EXISTS sites_fts;
+
+CREATE VIRTUAL TABLE sites_fts USING fts5(url, title, inner_text, annotations);
+
+INSERT INTO sites_fts (rowid, url, title, inner_text, annotations)
+SELECT s.id, s.url, s.title, s.inner_text, a.text
+  FROM sites s
+  LEFT JOIN annotations_text a ON a.site_id = s.id;
+
+DROP TRIGGER IF EXISTS passages_ai;
+
+DROP TRIGGER IF EXISTS passages_ad;
+
+DROP TABLE IF EXISTS passages_fts;
+
+DELETE FROM passages;
@@ -0,0 +1,15 @@
+CREATE VIRTUAL TABLE passages_fts USING fts5(text, content = 'passages', content_rowid = 'id');
+
+INSERT INTO passages_fts (passages_fts) VALUES ('rebuild');
+
+CREATE TRIGGER passages_ai AFTER INSERT ON passages
+    BEGIN
+        INSERT INTO passages_fts (rowid, text)
+        VALUES (new.id, new.text);
+    END;
+
+CREATE TRIGGER passages_ad AFTER DELETE ON passages
+    BEGIN
+        INSERT INTO passages_fts (passages_fts, rowid, text)
+        VALUES ('delete', old.id, old.text);
+    END;
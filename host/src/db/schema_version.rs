@@ -6,7 +6,7 @@ impl SchemaVersion {
         SchemaVersion(semver::Version::new(major, minor, patch))
     }
 
-    pub const CURRENT: SchemaVersion = SchemaVersion::new(0, 1, 0);
+    pub const CURRENT: SchemaVersion = SchemaVersion::new(0, 6, 0);
 
     pub fn major(&self) -> u64 {
         self.0.major
@@ -19,6 +19,10 @@ impl SchemaVersion {
     pub fn patch(&self) -> u64 {
         self.0.patch
     }
+
+    pub fn into_inner(self) -> semver::Version {
+        self.0
+    }
 }
 
 impl std::fmt::Display for SchemaVersion {
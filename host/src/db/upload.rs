@@ -0,0 +1,120 @@
+//! Saves whose text is sent in chunks.
+//!
+//! Chunks are kept in temporary tables, which belong to the connection and do not lock the
+//! database, until the upload is committed.
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::message::{
+    InnerText, SaveBeginRequestPayload, SaveChunkRequestPayload, SaveCommitRequestPayload,
+    SaveRequestPayload, Title, UploadId, Url,
+};
+
+const UPLOADS_SQL: &str = include_str!("uploads.sql");
+
+/// How long an upload is kept without being committed.
+const UPLOAD_RETENTION: &str = "-1 hours";
+
+pub fn create_tables(connection: &Connection) -> Result<(), rusqlite::Error> {
+    connection.execute_batch(UPLOADS_SQL)
+}
+
+fn delete(connection: &Connection, upload_id: UploadId) -> Result<(), rusqlite::Error> {
+    connection.execute("DELETE FROM upload_chunks WHERE upload_id = ?", [upload_id])?;
+    connection.execute("DELETE FROM uploads WHERE id = ?", [upload_id])?;
+    Ok(())
+}
+
+/// Starts an upload, discarding those that have been abandoned.
+pub fn begin(
+    connection: &Connection,
+    payload: &SaveBeginRequestPayload,
+) -> Result<UploadId, rusqlite::Error> {
+    let tx = connection.unchecked_transaction()?;
+    tx.execute(
+        "\
+DELETE FROM upload_chunks
+WHERE upload_id IN (SELECT id FROM uploads WHERE created_at < datetime('now', ?1))
+",
+        [UPLOAD_RETENTION],
+    )?;
+    tx.execute(
+        "DELETE FROM uploads WHERE created_at < datetime('now', ?1)",
+        [UPLOAD_RETENTION],
+    )?;
+    let upload_id = tx.query_row(
+        "INSERT INTO uploads (url, title) VALUES (?, ?) RETURNING id",
+        params![payload.url, payload.title],
+        |row| row.get(0),
+    )?;
+    tx.commit()?;
+    Ok(upload_id)
+}
+
+/// Adds a chunk to an upload, returning `false` if there is no such upload.
+pub fn add_chunk(
+    connection: &Connection,
+    payload: &SaveChunkRequestPayload,
+) -> Result<bool, rusqlite::Error> {
+    let changed = connection.execute(
+        "\
+INSERT OR REPLACE INTO upload_chunks (upload_id, chunk_index, text)
+SELECT id, ?, ? FROM uploads WHERE id = ?
+",
+        params![payload.index, payload.text, payload.upload_id],
+    )?;
+    Ok(changed > 0)
+}
+
+/// Saves the site of an upload with the text of its chunks, and discards the upload.
+///
+/// Returns `false`, saving nothing, if there is no such upload or it is missing chunks.
+pub fn commit(
+    connection: &Connection,
+    payload: &SaveCommitRequestPayload,
+) -> Result<bool, rusqlite::Error> {
+    let tx = connection.unchecked_transaction()?;
+    let upload: Option<(Url, Title)> = tx
+        .query_row(
+            "SELECT url, title FROM uploads WHERE id = ?",
+            [payload.upload_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((url, title)) = upload else {
+        return Ok(false);
+    };
+    let mut inner_text = String::new();
+    {
+        let mut statement = tx.prepare(
+            "\
+SELECT chunk_index, text
+FROM upload_chunks
+WHERE upload_id = ? AND chunk_index < ?
+ORDER BY chunk_index
+",
+        )?;
+        let mut rows = statement.query(params![payload.upload_id, payload.count])?;
+        let mut expected = 0;
+        while let Some(row) = rows.next()? {
+            let index: usize = row.get(0)?;
+            if index != expected {
+                return Ok(false);
+            }
+            inner_text.push_str(row.get_ref(1)?.as_str()?);
+            expected += 1;
+        }
+        if expected != payload.count {
+            return Ok(false);
+        }
+    }
+    let save_payload = SaveRequestPayload {
+        url,
+        title,
+        inner_text: InnerText::new(inner_text),
+    };
+    super::save_site(&tx, &save_payload)?;
+    delete(&tx, payload.upload_id)?;
+    tx.commit()?;
+    Ok(true)
+}
@@ -0,0 +1,82 @@
+//! Encodings of the messages within their length-prefixed frames.
+//!
+//! Browsers only speak JSON, but other clients can choose a binary encoding when they start the
+//! host, which spares encoding and escaping the text of large pages. Every encoding represents
+//! the same messages, so requests are decoded into JSON values whatever their encoding.
+
+use std::str::FromStr;
+
+use anyhow::Error;
+use serde::Serialize;
+use serde_json::Value;
+
+const MSG_UNKNOWN_ENCODING: &str = "Unknown encoding";
+
+/// The encoding of the messages exchanged with a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Encoding {
+    /// Decodes a message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` are not a message in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Result<Value, Error> {
+        let ret = match self {
+            Encoding::Json => serde_json::from_slice(bytes)?,
+            Encoding::MessagePack => rmp_serde::from_slice(bytes)?,
+            Encoding::Cbor => ciborium::from_reader(bytes)?,
+        };
+        Ok(ret)
+    }
+
+    /// Encodes a message.
+    ///
+    /// Structs are encoded as maps, so that a message has the same fields in every encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `message` cannot be encoded.
+    pub fn encode(self, message: &impl Serialize) -> Result<Vec<u8>, Error> {
+        let ret = match self {
+            Encoding::Json => serde_json::to_vec(message)?,
+            Encoding::MessagePack => rmp_serde::to_vec_named(message)?,
+            Encoding::Cbor => {
+                let mut ret = Vec::new();
+                ciborium::into_writer(message, &mut ret)?;
+                ret
+            }
+        };
+        Ok(ret)
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Encoding, Error> {
+        match s {
+            "json" => Ok(Encoding::Json),
+            "msgpack" => Ok(Encoding::MessagePack),
+            "cbor" => Ok(Encoding::Cbor),
+            other => Err(Error::msg(format!("{MSG_UNKNOWN_ENCODING}: {other}"))),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Encoding::Json => "json",
+            Encoding::MessagePack => "msgpack",
+            Encoding::Cbor => "cbor",
+        };
+        f.write_str(name)
+    }
+}
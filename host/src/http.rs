@@ -0,0 +1,312 @@
+//! A REST API for scripts, editors and launchers.
+//!
+//! - `GET /search?q=&page=&pageLength=&status=` returns the search header, with its `sites`.
+//! - `POST /sites` saves the site in the body, which is a `saveRequest` payload of at most
+//!   `max_request_size` bytes.
+//! - `DELETE /sites?url=` removes a site.
+//!
+//! Every request must carry the token in the data directory as a bearer token. Errors are
+//! returned as `{"error": message}`.
+//!
+//! The routes of [`opensearch`], for the browser's address bar, need no token, and are only
+//! served to clients on the loopback interface.
+
+mod opensearch;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+};
+
+use anyhow::Error;
+use noematic::{
+    Context,
+    message::{
+        ErrorResponsePayload, Query, RemoveRequestPayload, RequestAction, SaveRequestPayload,
+        SearchRequestPayload, Status, Url,
+    },
+};
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Server};
+
+use crate::{handle, search_sites};
+
+/// The name of the file within the data directory holding the token.
+const TOKEN_FILE: &str = "http-token";
+
+/// The number of random bytes in a token.
+const TOKEN_BYTES: usize = 32;
+
+/// The page length of searches which do not give one.
+const DEFAULT_PAGE_LENGTH: usize = 10;
+
+const MSG_UNAUTHORIZED: &str = "Missing or wrong bearer token";
+const MSG_NOT_LOOPBACK: &str = "Only available on the loopback interface";
+const MSG_NOT_FOUND: &str = "Not found";
+const MSG_METHOD_NOT_ALLOWED: &str = "Method not allowed";
+const MSG_MISSING_QUERY: &str = "Missing parameter: q";
+const MSG_MISSING_URL: &str = "Missing parameter: url";
+const MSG_INVALID_PARAMETER: &str = "Invalid parameter";
+const MSG_BODY_TOO_LARGE: &str = "Body too large";
+
+/// A request that could not be handled, and the status to report.
+struct Rejection {
+    status: u16,
+    message: String,
+}
+
+impl Rejection {
+    fn new(status: u16, message: impl Into<String>) -> Rejection {
+        let message = message.into();
+        Rejection { status, message }
+    }
+}
+
+/// The body of a successful response.
+struct Reply {
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(value: &Value) -> Reply {
+        let body = value.to_string();
+        Reply {
+            content_type: "application/json",
+            body,
+        }
+    }
+}
+
+impl From<Error> for Rejection {
+    fn from(err: Error) -> Rejection {
+        // An `errorResponse`, such as one listing invalid fields, is the client's to fix.
+        let status = if err.is::<ErrorResponsePayload>() {
+            400
+        } else {
+            500
+        };
+        Rejection::new(status, format!("{err:#}"))
+    }
+}
+
+/// Returns the token in `data_dir`, creating it if there is none, or if it is empty.
+///
+/// # Errors
+///
+/// Returns an error if the token cannot be read or created.
+pub fn token(data_dir: &Path) -> Result<String, Error> {
+    let path = data_dir.join(TOKEN_FILE);
+    match fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        // An empty token would be carried by every request without one.
+        Ok(_) => fs::remove_file(&path)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    let mut bytes = [0; TOKEN_BYTES];
+    getrandom::fill(&mut bytes)?;
+    let token = bytes.iter().fold(String::new(), |mut acc, byte| {
+        let _ = write!(acc, "{byte:02x}");
+        acc
+    });
+    fs::create_dir_all(data_dir)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path)?;
+    writeln!(file, "{token}")?;
+    Ok(token)
+}
+
+/// Compares in time independent of where `a` and `b` differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn is_authorized(request: &tiny_http::Request, token: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .filter(|header| header.field.equiv("Authorization"))
+        .filter_map(|header| header.value.as_str().strip_prefix("Bearer "))
+        .any(|value| constant_time_eq(value.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Returns whether the request came from the loopback interface, and was addressed to it.
+///
+/// Checking the `Host` header keeps pages on other sites from reading responses through DNS
+/// rebinding.
+fn is_loopback(request: &tiny_http::Request) -> bool {
+    let from_loopback = request
+        .remote_addr()
+        .is_some_and(|addr| addr.ip().is_loopback());
+    let to_loopback = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Host"))
+        .is_some_and(|header| {
+            let host = header.value.as_str();
+            let host = match host.strip_prefix('[') {
+                Some(rest) => rest.split_once(']').map_or(rest, |(host, _)| host),
+                None => host.split_once(':').map_or(host, |(host, _)| host),
+            };
+            host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+        });
+    from_loopback && to_loopback
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn parse_param<T: serde::de::DeserializeOwned>(
+    query: &str,
+    name: &str,
+) -> Result<Option<T>, Rejection> {
+    let Some(value) = query_param(query, name) else {
+        return Ok(None);
+    };
+    // Numbers and statuses are parsed as they would be in a message.
+    let value = value.parse().unwrap_or(Value::String(value));
+    serde_json::from_value(value)
+        .map(Some)
+        .map_err(|_| Rejection::new(400, format!("{MSG_INVALID_PARAMETER}: {name}")))
+}
+
+fn search(context: &mut Context, query: &str) -> Result<Reply, Rejection> {
+    let q = query_param(query, "q").ok_or_else(|| Rejection::new(400, MSG_MISSING_QUERY))?;
+    let payload = SearchRequestPayload {
+        query: Query::new(q),
+        page_num: parse_param(query, "page")?.unwrap_or(0),
+        page_length: parse_param(query, "pageLength")?.unwrap_or(DEFAULT_PAGE_LENGTH),
+        status: parse_param::<Status>(query, "status")?,
+    };
+    let (header, sites) = search_sites(context, payload)?;
+    let mut ret = serde_json::to_value(header).map_err(Error::from)?;
+    ret["sites"] = serde_json::to_value(sites).map_err(Error::from)?;
+    Ok(Reply::json(&ret))
+}
+
+/// Reads the body of `request`, which may be no larger than the largest request a client may
+/// send the host.
+fn read_body(context: &Context, request: &mut tiny_http::Request) -> Result<Vec<u8>, Rejection> {
+    let max_size = context.settings().max_request_size;
+    let too_large = || Rejection::new(413, format!("{MSG_BODY_TOO_LARGE}: over {max_size} bytes"));
+    if request
+        .body_length()
+        .is_some_and(|length| length > max_size as usize)
+    {
+        return Err(too_large());
+    }
+    let mut ret = Vec::new();
+    request
+        .as_reader()
+        .take(u64::from(max_size) + 1)
+        .read_to_end(&mut ret)
+        .map_err(|err| Rejection::new(400, err.to_string()))?;
+    if ret.len() > max_size as usize {
+        return Err(too_large());
+    }
+    Ok(ret)
+}
+
+fn save(context: &mut Context, request: &mut tiny_http::Request) -> Result<(), Rejection> {
+    let body = read_body(context, request)?;
+    let payload: SaveRequestPayload =
+        serde_json::from_slice(&body).map_err(|err| Rejection::new(400, err.to_string()))?;
+    handle(context, RequestAction::SaveRequest { payload })?;
+    Ok(())
+}
+
+fn remove(context: &mut Context, query: &str) -> Result<(), Rejection> {
+    let url = query_param(query, "url").ok_or_else(|| Rejection::new(400, MSG_MISSING_URL))?;
+    let payload = RemoveRequestPayload { url: Url::new(url) };
+    handle(context, RequestAction::RemoveRequest { payload })?;
+    Ok(())
+}
+
+/// Returns the body of the response to `request`, if it has one.
+///
+/// `base` is the URL of the server, for links to it.
+fn route(
+    context: &mut Context,
+    base: &str,
+    token: &str,
+    request: &mut tiny_http::Request,
+) -> Result<Option<Reply>, Rejection> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    if opensearch::is_route(path) {
+        if !is_loopback(request) {
+            return Err(Rejection::new(403, MSG_NOT_LOOPBACK));
+        }
+        if *request.method() != Method::Get {
+            return Err(Rejection::new(405, MSG_METHOD_NOT_ALLOWED));
+        }
+        return opensearch::route(context, base, path, query).map(Some);
+    }
+    if !is_authorized(request, token) {
+        return Err(Rejection::new(401, MSG_UNAUTHORIZED));
+    }
+    match (path, request.method()) {
+        ("/search", Method::Get) => search(context, query).map(Some),
+        ("/sites", Method::Post) => save(context, request).map(|()| None),
+        ("/sites", Method::Delete) => remove(context, query).map(|()| None),
+        ("/search" | "/sites", _) => Err(Rejection::new(405, MSG_METHOD_NOT_ALLOWED)),
+        _ => Err(Rejection::new(404, MSG_NOT_FOUND)),
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("valid header")
+}
+
+/// Serves the API on `bind`, printing the address it listens on.
+///
+/// # Errors
+///
+/// Returns an error if the server cannot listen on `bind`.
+pub fn serve(context: &mut Context, bind: SocketAddr, token: &str) -> Result<(), Error> {
+    let server = Server::http(bind).map_err(|err| Error::msg(err.to_string()))?;
+    let mut addr = server.server_addr().to_ip().unwrap_or(bind);
+    // Links are given to the browser, which cannot follow them to an unspecified address.
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        });
+    }
+    let base = format!("http://{addr}");
+    println!("Listening on {base}");
+    io::stdout().flush()?;
+    for mut request in server.incoming_requests() {
+        let response = match route(context, &base, token, &mut request) {
+            Ok(Some(reply)) => tiny_http::Response::from_string(reply.body)
+                .with_header(header("Content-Type", reply.content_type))
+                .boxed(),
+            Ok(None) => tiny_http::Response::empty(204).boxed(),
+            Err(rejection) => {
+                let body = json!({ "error": rejection.message });
+                let mut response = tiny_http::Response::from_string(body.to_string())
+                    .with_status_code(rejection.status)
+                    .with_header(header("Content-Type", "application/json"));
+                if rejection.status == 401 {
+                    response.add_header(header("WWW-Authenticate", "Bearer"));
+                }
+                response.boxed()
+            }
+        };
+        // The client may have gone away, which is no reason to stop serving.
+        let _ = request.respond(response);
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+//! A search engine description, suggestions and a results page, so that the index can be
+//! searched from the browser's address bar, following [OpenSearch].
+//!
+//! - `GET /opensearch.xml` returns the description, which browsers can discover from the results
+//!   page.
+//! - `GET /suggest?q=` returns suggestions, in the format of Firefox's search suggestions.
+//! - `GET /results?q=&page=` returns a page of results as HTML.
+//!
+//! [OpenSearch]: https://github.com/dewitt/opensearch
+
+use anyhow::Error;
+use noematic::{
+    Context,
+    message::{Query, SearchRequestPayload, SearchResponseSitePayload, Snippet},
+};
+use serde_json::json;
+
+use super::{Rejection, Reply, parse_param, query_param};
+use crate::search_sites;
+
+const ROUTES: [&str; 3] = ["/opensearch.xml", "/suggest", "/results"];
+
+/// The number of suggestions returned.
+const SUGGESTIONS: usize = 8;
+
+/// The number of results on each page.
+const RESULTS_PAGE_LENGTH: usize = 20;
+
+pub(super) fn is_route(path: &str) -> bool {
+    ROUTES.contains(&path)
+}
+
+fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Escapes a snippet, highlighting its matches with tags.
+fn escape_snippet(snippet: &Snippet) -> String {
+    escape(snippet.as_str())
+        .replace(Snippet::MATCH_START, "<b>")
+        .replace(Snippet::MATCH_END, "</b>")
+}
+
+fn strip_snippet(snippet: &Snippet) -> String {
+    snippet
+        .as_str()
+        .replace([Snippet::MATCH_START, Snippet::MATCH_END], "")
+}
+
+fn results_target(query: &str, page_num: usize) -> String {
+    let params = form_urlencoded::Serializer::new(String::new())
+        .append_pair("q", query)
+        .append_pair("page", &page_num.to_string())
+        .finish();
+    format!("/results?{params}")
+}
+
+fn description(base: &str) -> Reply {
+    let base = escape(base);
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <ShortName>Noematic</ShortName>
+  <Description>Search your saved pages</Description>
+  <InputEncoding>UTF-8</InputEncoding>
+  <Url type="text/html" method="get" template="{base}/results?q={{searchTerms}}"/>
+  <Url type="application/x-suggestions+json" method="get" template="{base}/suggest?q={{searchTerms}}"/>
+  <Url type="application/opensearchdescription+xml" rel="self" template="{base}/opensearch.xml"/>
+</OpenSearchDescription>
+"#
+    );
+    Reply {
+        content_type: "application/opensearchdescription+xml",
+        body,
+    }
+}
+
+/// Returns the sites matching `q`, or none if it is blank.
+fn search(
+    context: &mut Context,
+    q: &str,
+    page_num: usize,
+    page_length: usize,
+) -> Result<(bool, Vec<SearchResponseSitePayload>), Error> {
+    if q.trim().is_empty() {
+        return Ok((false, Vec::new()));
+    }
+    let payload = SearchRequestPayload {
+        query: Query::new(q.to_string()),
+        page_num,
+        page_length,
+        status: None,
+    };
+    let (header, sites) = search_sites(context, payload)?;
+    Ok((header.has_more, sites))
+}
+
+fn suggest(context: &mut Context, query: &str) -> Result<Reply, Rejection> {
+    let q = query_param(query, "q").unwrap_or_default();
+    let page_length = SUGGESTIONS.min(context.settings().max_page_length);
+    let (_, sites) = search(context, &q, 0, page_length)?;
+    let titles: Vec<&str> = sites.iter().map(|site| site.title.as_str()).collect();
+    let descriptions: Vec<String> = sites
+        .iter()
+        .map(|site| strip_snippet(&site.snippet))
+        .collect();
+    let urls: Vec<&str> = sites.iter().map(|site| site.url.as_str()).collect();
+    let body = json!([q, titles, descriptions, urls]).to_string();
+    Ok(Reply {
+        content_type: "application/x-suggestions+json",
+        body,
+    })
+}
+
+fn render_site(site: &SearchResponseSitePayload) -> String {
+    let url = site.url.as_str();
+    let title = escape(site.title.as_str());
+    // Other schemes, such as javascript:, are not followed from this page.
+    let link = if url.starts_with("http://") || url.starts_with("https://") {
+        format!(r#"<a href="{}">{title}</a>"#, escape(url))
+    } else {
+        title
+    };
+    format!(
+        "<li>{link}<br><small>{}</small><p>{}</p></li>\n",
+        escape(url),
+        escape_snippet(&site.snippet)
+    )
+}
+
+fn results(context: &mut Context, base: &str, query: &str) -> Result<Reply, Rejection> {
+    let q = query_param(query, "q").unwrap_or_default();
+    let page_num = parse_param(query, "page")?.unwrap_or(0);
+    let page_length = RESULTS_PAGE_LENGTH.min(context.settings().max_page_length);
+    let (has_more, sites) = search(context, &q, page_num, page_length)?;
+    let items: String = sites.iter().map(render_site).collect();
+    let mut nav = Vec::new();
+    if page_num > 0 {
+        let target = escape(&results_target(&q, page_num - 1));
+        nav.push(format!(r#"<a href="{target}" rel="prev">Previous</a>"#));
+    }
+    if has_more {
+        let target = escape(&results_target(&q, page_num + 1));
+        nav.push(format!(r#"<a href="{target}" rel="next">Next</a>"#));
+    }
+    let nav = nav.join(" ");
+    let start = page_num.saturating_mul(page_length).saturating_add(1);
+    let base = escape(base);
+    let q = escape(&q);
+    let body = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>{q} - Noematic</title>
+<link rel="search" type="application/opensearchdescription+xml" title="Noematic" href="{base}/opensearch.xml">
+</head>
+<body>
+<form action="/results" method="get">
+<input type="search" name="q" value="{q}" autofocus>
+<button>Search</button>
+</form>
+<ol start="{start}">
+{items}</ol>
+<nav>{nav}</nav>
+</body>
+</html>
+"#
+    );
+    Ok(Reply {
+        content_type: "text/html; charset=utf-8",
+        body,
+    })
+}
+
+/// Returns the response to a request for `path`, which is one of these routes.
+///
+/// `base` is the URL of the server, for links to it.
+pub(super) fn route(
+    context: &mut Context,
+    base: &str,
+    path: &str,
+    query: &str,
+) -> Result<Reply, Rejection> {
+    match path {
+        "/opensearch.xml" => Ok(description(base)),
+        "/suggest" => suggest(context, query),
+        "/results" => results(context, base, query),
+        _ => Err(Rejection::new(404, super::MSG_NOT_FOUND)),
+    }
+}
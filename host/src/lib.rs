@@ -1,22 +1,47 @@
 #![warn(clippy::pedantic)]
 #![deny(clippy::unwrap_in_result)]
 
+pub mod config;
 mod db;
+pub mod encoding;
 pub mod message;
+pub mod schema;
+mod validate;
 
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
 
 use anyhow::Error;
 use regex::Regex;
+use rusqlite::OpenFlags;
 use serde_json::Value;
 
+use config::Settings;
 use message::{
-    MessageVersion, Query, RemoveResponsePayload, Request, RequestAction, Response, ResponseAction,
-    SaveResponsePayload, SearchResponseHeaderPayload,
+    AddAnnotationResponsePayload, BackupResponsePayload, CancelResponsePayload, CancelStatus,
+    CapabilitiesResponsePayload, CorrelationId, EditAnnotationResponsePayload,
+    EmptyTrashResponsePayload, ErrorResponsePayload, FieldError, GetResponsePayload,
+    HelloRequestPayload, HelloResponsePayload, Limits, ListAnnotationsResponsePayload,
+    ListRequestPayload, ListResponseHeaderPayload, MaintenanceRequestPayload,
+    MaintenanceResponsePayload, MessageVersion, Query, RemoveAnnotationResponsePayload,
+    RemoveResponsePayload, Request, RequestAction, Response, ResponseAction, ResponseChunkPayload,
+    RestoreResponsePayload, SaveBeginResponsePayload, SaveChunkRequestPayload,
+    SaveChunkResponsePayload, SaveCommitRequestPayload, SaveResponsePayload, SearchRequestPayload,
+    SearchResponseHeaderPayload, SetStatusResponsePayload, StatsResponsePayload,
+    SubscribeResponsePayload, UploadId, VersionRange,
 };
 
 const FIELD_VERSION: &str = "version";
+const FIELD_ACTION: &str = "action";
+const FIELD_CORRELATION_ID: &str = "correlationId";
+const ACTION_HELLO_REQUEST: &str = "helloRequest";
 const MSG_MISSING_VERSION: &str = "Missing version";
+const MSG_INCOMPATIBLE_VERSION: &str = "Incompatible version";
+const MSG_REQUEST_TOO_LARGE: &str = "Request too large";
+const MSG_INVALID_UPLOAD: &str = "Unknown or incomplete upload";
+const MSG_INVALID_REQUEST: &str = "Invalid request";
+const MSG_CORRELATION_ID_TOO_LARGE: &str = "Correlation id too large to split response";
+const MSG_MISSING_KEY: &str = "Missing key file";
+const MSG_NOT_SEARCH_REQUEST: &str = "Not a search request";
 
 #[derive(Debug)]
 enum Connection {
@@ -32,9 +57,114 @@ impl AsRef<rusqlite::Connection> for Connection {
     }
 }
 
+/// A client's request to be told about changes to sites.
+struct Subscription {
+    version: MessageVersion,
+    correlation_id: CorrelationId,
+    /// The `data_version` when changes were last collected.
+    data_version: i64,
+    /// The id of the last change sent.
+    last_change: i64,
+}
+
+/// The optional features of the protocol supported by this host, which are offered to clients in
+/// a `helloResponse`.
+pub const CAPABILITIES: [&str; 2] = ["subscribe", "responseChunk"];
+
+/// The largest message, in bytes, that browsers accept from a native messaging host.
+pub const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// The longest escape of a character in a JSON string, `\uXXXX`.
+const MAX_ESCAPED_CHAR_LEN: usize = 6;
+
+/// The number of virtual machine instructions between checks of whether a search was cancelled.
+const CANCEL_CHECK_OPS: i32 = 1000;
+
+/// The state of one client of a [`Context`].
+#[derive(Default)]
+pub struct Session {
+    subscription: Option<Subscription>,
+    /// The uploads the client has begun and has yet to commit, which are the only ones it may
+    /// add chunks to or commit, as the uploads of every client are kept on the same connection.
+    uploads: HashSet<UploadId>,
+}
+
+impl Session {
+    #[must_use]
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    #[must_use]
+    pub fn is_subscribed(&self) -> bool {
+        self.subscription.is_some()
+    }
+}
+
 pub struct Context {
     connection: Connection,
     process: Box<dyn Fn(&Query) -> String>,
+    settings: Settings,
+    key: Option<String>,
+}
+
+/// A read-only connection to the database of a [`Context`], with which searches can be handled
+/// on another thread while the context handles other requests.
+pub struct Searcher {
+    connection: rusqlite::Connection,
+    process: Box<dyn Fn(&Query) -> String + Send>,
+    settings: Settings,
+}
+
+impl Searcher {
+    /// Handles a `searchRequest`, as [`handle_request`] would.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `request` is not a `searchRequest`, or the search fails.
+    pub fn handle_request(&self, request: Request) -> Result<Vec<Response>, Error> {
+        let errors = validate::validate(&request.action, &self.settings);
+        let Request {
+            version,
+            action,
+            correlation_id,
+        } = request;
+        let RequestAction::SearchRequest { payload } = action else {
+            return Err(Error::msg(MSG_NOT_SEARCH_REQUEST));
+        };
+        let actions = if errors.is_empty() {
+            search(
+                &self.connection,
+                self.process.as_ref(),
+                &self.settings,
+                payload,
+            )?
+        } else {
+            vec![invalid_fields(errors)]
+        };
+        Ok(responses(&version, &correlation_id, actions))
+    }
+
+    /// Handles a `searchRequest` as [`Searcher::handle_request`] does, stopping the search once
+    /// `cancelled` returns `true`, which is checked while it runs.
+    ///
+    /// Unlike an interrupt of the connection, which is lost if it comes before a statement
+    /// starts, this also stops a search that has yet to query the database.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `request` is not a `searchRequest`, or the search fails or is stopped.
+    pub fn handle_cancellable_request(
+        &self,
+        request: Request,
+        cancelled: impl FnMut() -> bool + Send + 'static,
+    ) -> Result<Vec<Response>, Error> {
+        self.connection
+            .progress_handler(CANCEL_CHECK_OPS, Some(cancelled))?;
+        let ret = self.handle_request(request);
+        self.connection.progress_handler(0, None::<fn() -> bool>)?;
+        ret
+    }
 }
 
 fn make_process(re: Regex) -> impl Fn(&Query) -> String {
@@ -45,114 +175,538 @@ fn make_process(re: Regex) -> impl Fn(&Query) -> String {
 }
 
 impl Context {
-    const REGEX_WHITESPACE: &'static str = r"\W+";
-
+    /// Opens the database given by `settings`, which is in memory if `settings.db` is `None`.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the database initialization or regex compilation fails.
-    pub fn in_memory() -> Result<Context, Error> {
-        let mut connection = rusqlite::Connection::open_in_memory()?;
-        db::init_tables(&mut connection)?;
-        let connection = Connection::InMemory(connection);
-        let process_regex = Regex::new(Context::REGEX_WHITESPACE)?;
+    /// Returns an error if the settings are invalid, or the database cannot be opened or
+    /// initialized.
+    pub fn new(settings: Settings) -> Result<Context, Error> {
+        settings.validate()?;
+        let (connection, key) = match &settings.db {
+            None => {
+                let mut connection = rusqlite::Connection::open_in_memory()?;
+                db::init_tables(&mut connection)?;
+                (Connection::InMemory(connection), None)
+            }
+            Some(db_path) => {
+                let key = settings.key()?;
+                let mut connection =
+                    db::encryption::open(db_path, OpenFlags::default(), key.as_deref())?;
+                db::configure(&mut connection)?;
+                db::backup_if_migrating(&connection, db_path, key.as_deref())?;
+                db::init_tables(&mut connection)?;
+                (Connection::Persistent(connection), key)
+            }
+        };
+        db::upload::create_tables(connection.as_ref())?;
+        let process_regex = settings.query_regex()?;
         let process = Box::new(make_process(process_regex));
         let context = Context {
             connection,
             process,
+            settings,
+            key,
         };
+        db::retry(|| db::prune_changes(context.connection.as_ref()))?;
         Ok(context)
     }
 
+    #[must_use]
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Opens a [`Searcher`] on the database, or returns `None` if the database is in memory, and
+    /// so cannot be shared with another connection.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the database cannot be opened, initialization fails, or regex compilation fails.
-    pub fn persistent(db_path: impl AsRef<Path>) -> Result<Context, Error> {
-        let mut connection = rusqlite::Connection::open(db_path.as_ref())?;
-        db::init_tables(&mut connection)?;
-        let connection = Connection::Persistent(connection);
-        let process_regex = Regex::new(Context::REGEX_WHITESPACE)?;
-        let process = Box::new(make_process(process_regex));
-        let context = Context {
+    /// Returns an error if the database cannot be opened.
+    pub fn searcher(&self) -> Result<Option<Searcher>, Error> {
+        let (Connection::Persistent(_), Some(db_path)) = (&self.connection, &self.settings.db)
+        else {
+            return Ok(None);
+        };
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connection = db::encryption::open(db_path, flags, self.key.as_deref())?;
+        db::configure_reader(&connection)?;
+        let process = Box::new(make_process(self.settings.query_regex()?));
+        Ok(Some(Searcher {
             connection,
             process,
+            settings: self.settings.clone(),
+        }))
+    }
+
+    fn subscribe(
+        &self,
+        session: &mut Session,
+        version: MessageVersion,
+        correlation_id: CorrelationId,
+    ) -> Result<(), Error> {
+        let connection = self.connection.as_ref();
+        session.subscription = Some(Subscription {
+            version,
+            correlation_id,
+            data_version: db::data_version(connection)?,
+            last_change: db::last_change(connection)?,
+        });
+        Ok(())
+    }
+
+    /// Returns a `siteChanged` event for each change made since the last were collected for
+    /// `session`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operations fail.
+    pub fn collect_changes(&self, session: &mut Session) -> Result<Vec<Response>, Error> {
+        let connection = self.connection.as_ref();
+        let Some(subscription) = &mut session.subscription else {
+            return Ok(Vec::new());
         };
-        Ok(context)
+        subscription.data_version = db::data_version(connection)?;
+        let changes = db::changes_since(connection, subscription.last_change)?;
+        let mut ret = Vec::with_capacity(changes.len());
+        for (id, payload) in changes {
+            subscription.last_change = id;
+            ret.push(Response {
+                version: subscription.version.clone(),
+                action: ResponseAction::SiteChanged { payload },
+                correlation_id: subscription.correlation_id.clone(),
+            });
+        }
+        Ok(ret)
+    }
+
+    /// Returns events for changes made by other connections since the last were collected for
+    /// `session`.
+    ///
+    /// This is cheap when nothing has changed, and is meant to be called periodically while a
+    /// client is subscribed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operations fail.
+    pub fn poll_changes(&self, session: &mut Session) -> Result<Vec<Response>, Error> {
+        let Some(subscription) = &session.subscription else {
+            return Ok(Vec::new());
+        };
+        if db::data_version(self.connection.as_ref())? == subscription.data_version {
+            return Ok(Vec::new());
+        }
+        self.collect_changes(session)
+    }
+
+    /// Permanently removes sites that have been in the trash for longer than the configured
+    /// retention period.
+    ///
+    /// Returns the number of sites removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operation fails.
+    pub fn purge_trash(&self) -> Result<usize, Error> {
+        let retention_days = self.settings.trash_retention_days;
+        let connection = self.connection.as_ref();
+        let removed = db::retry(|| db::purge_trash(connection, retention_days))?;
+        Ok(removed)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the database operations fail.
+    pub fn stats(&self) -> Result<StatsResponsePayload, Error> {
+        db::stats(self.connection.as_ref())
+    }
+
+    /// Checks the database and its full-text indexes, optionally repairing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database operations fail.
+    pub fn maintain(
+        &self,
+        payload: &MaintenanceRequestPayload,
+    ) -> Result<MaintenanceResponsePayload, Error> {
+        let connection = self.connection.as_ref();
+        let before = db::maintenance::check(connection)?;
+        if payload.rebuild {
+            db::maintenance::rebuild(connection)?;
+        }
+        if payload.optimize {
+            db::maintenance::optimize(connection)?;
+        }
+        let after = if payload.rebuild || payload.optimize {
+            Some(db::maintenance::check(connection)?)
+        } else {
+            None
+        };
+        Ok(MaintenanceResponsePayload {
+            before,
+            after,
+            rebuilt: payload.rebuild,
+            optimized: payload.optimize,
+        })
+    }
+
+    /// Writes a verified copy of the database to a new file at `path` using the online backup API.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file exists at `path`, the backup fails, or the copy does not pass
+    /// verification.
+    pub fn backup(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        db::backup::backup(self.connection.as_ref(), path.as_ref(), self.key.as_deref())
     }
 }
 
-/// # Errors
-///
-/// Returns an error if the database operations fail.
-pub fn handle_request(context: &mut Context, request: Request) -> Result<Vec<Response>, Error> {
-    let version = request.version;
-    let correlation_id = request.correlation_id;
+fn search(
+    connection: &rusqlite::Connection,
+    process: &dyn Fn(&Query) -> String,
+    settings: &Settings,
+    payload: SearchRequestPayload,
+) -> Result<Vec<ResponseAction>, Error> {
+    let snippet_tokens = settings.snippet_tokens;
+    let (results, has_more) = db::search_sites(connection, &payload, process, snippet_tokens)?;
+    let header = SearchResponseHeaderPayload {
+        query: payload.query,
+        page_num: payload.page_num,
+        page_length: results.len(),
+        has_more,
+    };
+    let mut ret = vec![ResponseAction::SearchResponseHeader { payload: header }];
+    for payload in results {
+        ret.push(ResponseAction::SearchResponseSite { payload });
+    }
+    Ok(ret)
+}
 
+fn list(context: &Context, payload: &ListRequestPayload) -> Result<Vec<ResponseAction>, Error> {
     let connection = context.connection.as_ref();
+    let (results, has_more) = db::list_sites(connection, payload)?;
+    let header = ListResponseHeaderPayload {
+        page_num: payload.page_num,
+        page_length: results.len(),
+        has_more,
+    };
+    let mut ret = vec![ResponseAction::ListResponseHeader { payload: header }];
+    for payload in results {
+        ret.push(ResponseAction::ListResponseSite { payload });
+    }
+    Ok(ret)
+}
+
+fn incompatible_version(message: String) -> ResponseAction {
+    let payload = ErrorResponsePayload::IncompatibleVersion {
+        message,
+        supported: VersionRange::SUPPORTED,
+    };
+    ResponseAction::ErrorResponse { payload }
+}
+
+fn invalid_upload(upload_id: UploadId) -> ResponseAction {
+    let payload = ErrorResponsePayload::InvalidUpload {
+        message: format!("{MSG_INVALID_UPLOAD}: {upload_id}"),
+        upload_id,
+    };
+    ResponseAction::ErrorResponse { payload }
+}
+
+fn invalid_request(message: String, fields: Vec<FieldError>) -> ResponseAction {
+    let payload = ErrorResponsePayload::InvalidRequest { message, fields };
+    ResponseAction::ErrorResponse { payload }
+}
 
-    match request.action {
+fn invalid_fields(fields: Vec<FieldError>) -> ResponseAction {
+    let details: Vec<String> = fields
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect();
+    invalid_request(
+        format!("{MSG_INVALID_REQUEST}: {}", details.join("; ")),
+        fields,
+    )
+}
+
+fn save_chunk(
+    connection: &rusqlite::Connection,
+    session: &Session,
+    payload: &SaveChunkRequestPayload,
+) -> Result<ResponseAction, Error> {
+    let owned = session.uploads.contains(&payload.upload_id);
+    if !owned || !db::upload::add_chunk(connection, payload)? {
+        return Ok(invalid_upload(payload.upload_id));
+    }
+    let payload = SaveChunkResponsePayload {};
+    Ok(ResponseAction::SaveChunkResponse { payload })
+}
+
+fn save_commit(
+    connection: &rusqlite::Connection,
+    session: &mut Session,
+    payload: &SaveCommitRequestPayload,
+) -> Result<ResponseAction, Error> {
+    let owned = session.uploads.contains(&payload.upload_id);
+    if !owned || !db::retry(|| db::upload::commit(connection, payload))? {
+        return Ok(invalid_upload(payload.upload_id));
+    }
+    session.uploads.remove(&payload.upload_id);
+    let payload = SaveResponsePayload {};
+    Ok(ResponseAction::SaveResponse { payload })
+}
+
+fn hello(payload: HelloRequestPayload) -> ResponseAction {
+    let Some(version) = payload.versions.negotiate() else {
+        let VersionRange { min, max } = payload.versions;
+        return incompatible_version(format!("{MSG_INCOMPATIBLE_VERSION}: {min} to {max}"));
+    };
+    let payload = HelloResponsePayload {
+        version,
+        versions: VersionRange::SUPPORTED,
+        capabilities: CAPABILITIES.map(String::from).to_vec(),
+    };
+    ResponseAction::HelloResponse { payload }
+}
+
+fn capabilities(settings: &Settings) -> CapabilitiesResponsePayload {
+    let mut features = Vec::new();
+    if cfg!(feature = "encryption") {
+        features.push("encryption".to_string());
+    }
+    CapabilitiesResponsePayload {
+        host_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: db::current_version(),
+        request_actions: schema::request_actions().to_vec(),
+        fts_tokenizer: db::FTS_TOKENIZER.to_string(),
+        features,
+        limits: Limits {
+            max_request_size: settings.max_request_size,
+            max_response_size: MAX_RESPONSE_SIZE,
+            max_page_length: settings.max_page_length,
+        },
+    }
+}
+
+fn dispatch(
+    context: &mut Context,
+    session: &mut Session,
+    action: RequestAction,
+) -> Result<Vec<ResponseAction>, Error> {
+    let connection = context.connection.as_ref();
+
+    let action = match action {
         RequestAction::SaveRequest { payload } => {
-            db::upsert_site(connection, &payload)?;
-            let response = {
-                let payload = SaveResponsePayload {};
-                let action = ResponseAction::SaveResponse { payload };
-                Response {
-                    version,
-                    action,
-                    correlation_id,
-                }
-            };
-            Ok(vec![response])
+            db::retry(|| db::upsert_site(connection, &payload))?;
+            let payload = SaveResponsePayload {};
+            ResponseAction::SaveResponse { payload }
         }
         RequestAction::RemoveRequest { payload } => {
-            db::remove(connection, &payload)?;
-            let response = {
-                let payload = RemoveResponsePayload {};
-                let action = ResponseAction::RemoveResponse { payload };
-                Response {
-                    version,
-                    action,
-                    correlation_id,
-                }
-            };
-            Ok(vec![response])
+            db::retry(|| db::remove(connection, &payload))?;
+            let payload = RemoveResponsePayload {};
+            ResponseAction::RemoveResponse { payload }
         }
         RequestAction::SearchRequest { payload } => {
             let process = context.process.as_ref();
-            let query = payload.query.clone();
-            let page_num = payload.page_num;
-            let (results, has_more) = db::search_sites(connection, &payload, process)?;
-            let header = {
-                let page_length = results.len();
-                let version = version.clone();
-                let correlation_id = correlation_id.clone();
-                let payload = SearchResponseHeaderPayload {
-                    query,
-                    page_num,
-                    page_length,
-                    has_more,
-                };
-                let action = ResponseAction::SearchResponseHeader { payload };
-                Response {
-                    version,
-                    action,
-                    correlation_id,
-                }
-            };
-            let mut ret = vec![header];
-            for payload in results {
-                let version = version.clone();
-                let correlation_id = correlation_id.clone();
-                let action = ResponseAction::SearchResponseSite { payload };
-                let response = Response {
-                    version,
-                    action,
-                    correlation_id,
-                };
-                ret.push(response);
-            }
-            Ok(ret)
+            return search(connection, process, &context.settings, payload);
+        }
+        RequestAction::AddAnnotationRequest { payload } => {
+            let annotation = db::retry(|| db::insert_annotation(connection, &payload))?;
+            let payload = AddAnnotationResponsePayload { annotation };
+            ResponseAction::AddAnnotationResponse { payload }
+        }
+        RequestAction::EditAnnotationRequest { payload } => {
+            let annotation = db::retry(|| db::update_annotation(connection, &payload))?;
+            let payload = EditAnnotationResponsePayload { annotation };
+            ResponseAction::EditAnnotationResponse { payload }
+        }
+        RequestAction::RemoveAnnotationRequest { payload } => {
+            db::retry(|| db::delete_annotation(connection, &payload))?;
+            let payload = RemoveAnnotationResponsePayload {};
+            ResponseAction::RemoveAnnotationResponse { payload }
+        }
+        RequestAction::ListAnnotationsRequest { payload } => {
+            let annotations = db::list_annotations(connection, &payload)?;
+            let payload = ListAnnotationsResponsePayload { annotations };
+            ResponseAction::ListAnnotationsResponse { payload }
+        }
+        RequestAction::SetStatusRequest { payload } => {
+            db::retry(|| db::set_status(connection, &payload))?;
+            let payload = SetStatusResponsePayload {};
+            ResponseAction::SetStatusResponse { payload }
+        }
+        RequestAction::ListRequest { payload } => return list(context, &payload),
+        RequestAction::RestoreRequest { payload } => {
+            db::retry(|| db::restore(connection, &payload))?;
+            let payload = RestoreResponsePayload {};
+            ResponseAction::RestoreResponse { payload }
+        }
+        RequestAction::EmptyTrashRequest { payload: _ } => {
+            let removed = db::retry(|| db::empty_trash(connection))?;
+            let payload = EmptyTrashResponsePayload { removed };
+            ResponseAction::EmptyTrashResponse { payload }
+        }
+        RequestAction::StatsRequest { payload: _ } => {
+            let payload = context.stats()?;
+            ResponseAction::StatsResponse { payload }
+        }
+        RequestAction::BackupRequest { payload } => {
+            context.backup(&payload.path)?;
+            let payload = BackupResponsePayload {};
+            ResponseAction::BackupResponse { payload }
+        }
+        RequestAction::MaintenanceRequest { payload } => {
+            let payload = context.maintain(&payload)?;
+            ResponseAction::MaintenanceResponse { payload }
         }
+        RequestAction::ConfigRequest { payload: _ } => {
+            let payload = context.settings.clone();
+            ResponseAction::ConfigResponse { payload }
+        }
+        RequestAction::GetRequest { payload } => {
+            let site = db::get_site(connection, &payload)?;
+            let payload = GetResponsePayload { site };
+            ResponseAction::GetResponse { payload }
+        }
+        RequestAction::SubscribeRequest { payload: _ } => {
+            let payload = SubscribeResponsePayload {};
+            ResponseAction::SubscribeResponse { payload }
+        }
+        RequestAction::HelloRequest { payload } => hello(payload),
+        RequestAction::SaveBeginRequest { payload } => {
+            let upload_id = db::retry(|| db::upload::begin(connection, &payload))?;
+            session.uploads.insert(upload_id);
+            let payload = SaveBeginResponsePayload { upload_id };
+            ResponseAction::SaveBeginResponse { payload }
+        }
+        RequestAction::SaveChunkRequest { payload } => save_chunk(connection, session, &payload)?,
+        RequestAction::SaveCommitRequest { payload } => save_commit(connection, session, &payload)?,
+        RequestAction::CapabilitiesRequest { payload: _ } => {
+            let payload = capabilities(&context.settings);
+            ResponseAction::CapabilitiesResponse { payload }
+        }
+        // Searches handled here finish before the next request is read, so none can be running.
+        RequestAction::CancelRequest { payload } => cancelled(payload.correlation_id, false),
+    };
+    Ok(vec![action])
+}
+
+fn cancelled(correlation_id: CorrelationId, cancelled: bool) -> ResponseAction {
+    let status = if cancelled {
+        CancelStatus::Cancelled
+    } else {
+        CancelStatus::NotRunning
+    };
+    let payload = CancelResponsePayload {
+        correlation_id,
+        status,
+    };
+    ResponseAction::CancelResponse { payload }
+}
+
+/// Answers `request` if it is a `cancelRequest`, calling `cancel` with the correlation id of the
+/// search to stop, which returns whether the search had yet to finish.
+pub fn cancel(request: &Request, cancel: impl FnOnce(&CorrelationId) -> bool) -> Option<Response> {
+    let RequestAction::CancelRequest { payload } = &request.action else {
+        return None;
+    };
+    let action = cancelled(
+        payload.correlation_id.clone(),
+        cancel(&payload.correlation_id),
+    );
+    Some(Response {
+        version: request.version.clone(),
+        action,
+        correlation_id: request.correlation_id.clone(),
+    })
+}
+
+fn responses(
+    version: &MessageVersion,
+    correlation_id: &CorrelationId,
+    actions: Vec<ResponseAction>,
+) -> Vec<Response> {
+    actions
+        .into_iter()
+        .map(|action| Response {
+            version: version.clone(),
+            action,
+            correlation_id: correlation_id.clone(),
+        })
+        .collect()
+}
+
+/// # Errors
+///
+/// Returns an error if the database operations fail.
+pub fn handle_request(
+    context: &mut Context,
+    session: &mut Session,
+    request: Request,
+) -> Result<Vec<Response>, Error> {
+    // The version of a `helloRequest` may not be one this host accepts.
+    let version = match request.action {
+        RequestAction::HelloRequest { .. } => MessageVersion::EXPECTED,
+        _ => request.version,
+    };
+    let correlation_id = request.correlation_id;
+    let errors = validate::validate(&request.action, &context.settings);
+    if !errors.is_empty() {
+        let actions = vec![invalid_fields(errors)];
+        return Ok(responses(&version, &correlation_id, actions));
+    }
+    let subscribing = matches!(request.action, RequestAction::SubscribeRequest { .. });
+    let actions = dispatch(context, session, request.action)?;
+    let mut responses = responses(&version, &correlation_id, actions);
+    if subscribing {
+        context.subscribe(session, version, correlation_id)?;
+    } else {
+        // Changes made by the request are reported after its responses.
+        responses.extend(context.collect_changes(session)?);
     }
+    Ok(responses)
+}
+
+/// Replaces the contents of the database at `db_path` with the backup at `src`, which is
+/// encrypted with the configured key if there is one.
+///
+/// The backup is checked for integrity and schema compatibility before it is copied, and other
+/// processes using the database wait for the copy to finish. The database is first backed up
+/// into the `backups` directory beside it, where the three most recent such backups are kept.
+///
+/// # Errors
+///
+/// Returns an error if the backup is corrupt, was written by a newer version, or cannot be copied.
+pub fn restore(
+    settings: &Settings,
+    db_path: impl AsRef<Path>,
+    src: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let key = settings.key()?;
+    db::backup::restore(db_path.as_ref(), src.as_ref(), key.as_deref())
+}
+
+/// Encrypts the plaintext database at `db_path` with the configured key.
+///
+/// # Errors
+///
+/// Returns an error if there is no key, encryption is not supported by this build, or the
+/// database cannot be read.
+pub fn encrypt(settings: &Settings, db_path: impl AsRef<Path>) -> Result<(), Error> {
+    let key = settings.key()?.ok_or_else(|| Error::msg(MSG_MISSING_KEY))?;
+    db::encryption::encrypt(db_path.as_ref(), &key)
+}
+
+/// Decrypts the database at `db_path`, which is encrypted with the configured key.
+///
+/// # Errors
+///
+/// Returns an error if there is no key, encryption is not supported by this build, or the key is
+/// wrong.
+pub fn decrypt(settings: &Settings, db_path: impl AsRef<Path>) -> Result<(), Error> {
+    let key = settings.key()?.ok_or_else(|| Error::msg(MSG_MISSING_KEY))?;
+    db::encryption::decrypt(db_path.as_ref(), &key)
 }
 
 /// # Errors
@@ -165,3 +719,148 @@ pub fn extract_version(value: &Value) -> Result<MessageVersion, Error> {
     let version = MessageVersion::parse(version)?;
     Ok(version)
 }
+
+/// Returns an `errorResponse` to `message` if this host does not accept its version.
+///
+/// A `helloRequest` is accepted whatever its version.
+///
+/// # Errors
+///
+/// Returns an error if the version field is missing or cannot be parsed.
+pub fn check_version(message: &Value) -> Result<Option<Response>, Error> {
+    let version = extract_version(message)?;
+    if VersionRange::SUPPORTED.accepts(&version) || message[FIELD_ACTION] == ACTION_HELLO_REQUEST {
+        return Ok(None);
+    }
+    Ok(Some(Response {
+        version: MessageVersion::EXPECTED,
+        action: incompatible_version(format!("{MSG_INCOMPATIBLE_VERSION}: {version}")),
+        correlation_id: extract_correlation_id(message),
+    }))
+}
+
+/// Returns the correlation id of `message`, or an empty one if it has none.
+fn extract_correlation_id(message: &Value) -> CorrelationId {
+    let correlation_id = message[FIELD_CORRELATION_ID].as_str().unwrap_or_default();
+    CorrelationId::new(correlation_id.to_string())
+}
+
+/// Parses `message`, whose version has been checked, returning an `errorResponse` instead if it
+/// is not a request.
+///
+/// # Errors
+///
+/// Returns the `errorResponse` if `message` cannot be parsed as a request.
+pub fn parse_request(message: Value) -> Result<Request, Box<Response>> {
+    let correlation_id = extract_correlation_id(&message);
+    let version = extract_version(&message).unwrap_or(MessageVersion::EXPECTED);
+    serde_json::from_value(message).map_err(|err| {
+        let action = invalid_request(format!("{MSG_INVALID_REQUEST}: {err}"), Vec::new());
+        Box::new(Response {
+            version,
+            action,
+            correlation_id,
+        })
+    })
+}
+
+/// Returns the correlation id found in `parts` of a JSON message which was not parsed, such as
+/// its start and end, or an empty one if none of them has it.
+#[must_use]
+pub fn find_correlation_id(parts: &[&[u8]]) -> CorrelationId {
+    let key = format!("\"{FIELD_CORRELATION_ID}\"");
+    let key = key.as_bytes();
+    // A key is never escaped, whereas quotation marks within a string are.
+    let correlation_id = parts.iter().find_map(|part| {
+        let start = part.windows(key.len()).rposition(|window| window == key)?;
+        let rest = part[start + key.len()..]
+            .trim_ascii_start()
+            .strip_prefix(b":")?;
+        serde_json::Deserializer::from_slice(rest)
+            .into_iter::<String>()
+            .next()?
+            .ok()
+    });
+    CorrelationId::new(correlation_id.unwrap_or_default())
+}
+
+/// Returns an `errorResponse` to a request of `size` bytes, which was skipped because it was
+/// larger than `max_size`.
+#[must_use]
+pub fn request_too_large(size: u32, max_size: u32, correlation_id: CorrelationId) -> Response {
+    let payload = ErrorResponsePayload::RequestTooLarge {
+        message: format!("{MSG_REQUEST_TOO_LARGE}: {size} > {max_size} bytes"),
+        size,
+        max_size,
+    };
+    Response {
+        version: MessageVersion::EXPECTED,
+        action: ResponseAction::ErrorResponse { payload },
+        correlation_id,
+    }
+}
+
+fn response_chunk(response: &Response, index: usize, count: usize, data: String) -> Response {
+    let payload = ResponseChunkPayload { index, count, data };
+    Response {
+        version: response.version.clone(),
+        action: ResponseAction::ResponseChunk { payload },
+        correlation_id: response.correlation_id.clone(),
+    }
+}
+
+/// Returns the length of `c` in a JSON string.
+fn escaped_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\u{8}' | '\u{c}' | '\n' | '\r' | '\t' => 2,
+        c if c < ' ' => MAX_ESCAPED_CHAR_LEN,
+        c => c.len_utf8(),
+    }
+}
+
+/// Splits `text` into parts which are at most `max_len` bytes long in a JSON string.
+fn split_escaped(text: &str, max_len: usize) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut part = String::new();
+    let mut part_len = 0;
+    for c in text.chars() {
+        let len = escaped_len(c);
+        if part_len + len > max_len {
+            ret.push(std::mem::take(&mut part));
+            part_len = 0;
+        }
+        part.push(c);
+        part_len += len;
+    }
+    ret.push(part);
+    ret
+}
+
+/// Serializes `response` as a message, or if that would be larger than `max_size` bytes, as
+/// `responseChunk` messages which are not.
+///
+/// # Errors
+///
+/// Returns an error if the response cannot be serialized, or its correlation id leaves no room
+/// for data in a chunk.
+pub fn serialize_response(response: &Response, max_size: usize) -> Result<Vec<Vec<u8>>, Error> {
+    let json = serde_json::to_string(response)?;
+    if json.len() <= max_size {
+        return Ok(vec![json.into_bytes()]);
+    }
+    // The largest chunk without data, which has the longest index and count.
+    let empty = response_chunk(response, usize::MAX, usize::MAX, String::new());
+    let max_len = max_size.saturating_sub(serde_json::to_string(&empty)?.len());
+    if max_len < MAX_ESCAPED_CHAR_LEN {
+        return Err(Error::msg(MSG_CORRELATION_ID_TOO_LARGE));
+    }
+    let parts = split_escaped(&json, max_len);
+    let count = parts.len();
+    let mut ret = Vec::with_capacity(count);
+    for (index, data) in parts.into_iter().enumerate() {
+        ret.push(serde_json::to_vec(&response_chunk(
+            response, index, count, data,
+        ))?);
+    }
+    Ok(ret)
+}
@@ -1,39 +1,189 @@
 #![warn(clippy::pedantic)]
 #![deny(clippy::unwrap_in_result)]
 
+#[cfg(unix)]
+mod daemon;
+mod http;
+mod pool;
+mod rpc;
+
 use std::{
+    collections::HashMap,
     fs,
-    io::{self, BufRead, BufReader, BufWriter, Write},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
     mem,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvError, RecvTimeoutError, SyncSender},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use directories::ProjectDirs;
 use serde_json::Value;
 
 use noematic::{
-    Context,
-    message::{MessageVersion, Request},
+    Context, Session,
+    config::{CONFIG_FILE, Overrides, Settings},
+    encoding::Encoding,
+    message::{
+        CorrelationId, MaintenanceRequestPayload, MessageVersion, Request, RequestAction, Response,
+        ResponseAction, SearchRequestPayload, SearchResponseHeaderPayload,
+        SearchResponseSitePayload,
+    },
 };
 
+use crate::pool::{Callback, Cancel, POOL_SIZE, SearchPool};
+
 // We use unchecked casts to convert u32 to usize.
 const _: () = assert!(mem::size_of::<usize>() >= mem::size_of::<u32>());
 
+/// How often a subscribed host, or a daemon, checks for changes made by other processes.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
 const MSG_MISSING_HOME_DIR: &str = "Missing home directory";
-const MSG_UNSUPPORTED_VERSION: &str = "Unsupported version";
 const MSG_UNSUPPORTED_LENGTH: &str = "Unsupported length";
+const MSG_IN_MEMORY: &str = "Cannot run this command on an in-memory database";
+const MSG_UNHEALTHY: &str = "Database is unhealthy";
+const MSG_MISSING_HEADER: &str = "Missing search response header";
+const MSG_WRITER_STOPPED: &str = "Writer thread stopped";
+#[cfg(not(unix))]
+const MSG_NO_DAEMON: &str = "This platform does not support the daemon";
+
+/// The number of messages waiting to be handled, or responses waiting to be written, at which
+/// the host stops reading messages.
+const QUEUE_LENGTH: usize = 16;
+
+/// The number of bytes kept from each end of a message that is too large, in which to find its
+/// correlation id.
+const EDGE_LENGTH: usize = 1024;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Run with in-memory database
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     test: bool,
+    /// Path of the database, overriding --profile
+    #[arg(long, global = true, value_name = "PATH", env = "NOEMATIC_DB")]
+    db: Option<PathBuf>,
+    /// File containing the passphrase of an encrypted database
+    #[arg(long, global = true, value_name = "PATH", env = "NOEMATIC_KEY_FILE")]
+    key_file: Option<PathBuf>,
+    /// Use the database of a profile from the config file
+    #[arg(long, global = true, value_name = "NAME", env = "NOEMATIC_PROFILE")]
+    profile: Option<String>,
+    /// Path of the config file
+    #[arg(long, global = true, value_name = "PATH", env = "NOEMATIC_CONFIG")]
+    config: Option<PathBuf>,
+    /// Days to keep removed sites in the trash before purging them [default: 30]
+    #[arg(
+        long,
+        global = true,
+        value_name = "DAYS",
+        env = "NOEMATIC_TRASH_RETENTION_DAYS"
+    )]
+    trash_retention_days: Option<u32>,
+    /// Pattern whose matches are replaced by spaces in queries [default: \W+]
+    #[arg(
+        long,
+        global = true,
+        value_name = "REGEX",
+        env = "NOEMATIC_QUERY_REGEX"
+    )]
+    query_regex: Option<String>,
+    /// Maximum number of tokens in a snippet, from 1 to 64 [default: 40]
+    #[arg(long, global = true, value_name = "N", env = "NOEMATIC_SNIPPET_TOKENS")]
+    snippet_tokens: Option<u32>,
+    /// Maximum number of results in a page [default: 100]
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        env = "NOEMATIC_MAX_PAGE_LENGTH"
+    )]
+    max_page_length: Option<usize>,
+    /// Largest request, in bytes, that is read [default: 67108864]
+    #[arg(
+        long,
+        global = true,
+        value_name = "BYTES",
+        env = "NOEMATIC_MAX_REQUEST_SIZE"
+    )]
+    max_request_size: Option<u32>,
+    /// Encoding of messages: json, msgpack or cbor. Binary encodings are handled without a daemon
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        env = "NOEMATIC_ENCODING",
+        default_value_t
+    )]
+    encoding: Encoding,
+    /// Handle messages in this process instead of forwarding them to a daemon
+    #[arg(long, global = true, env = "NOEMATIC_NO_DAEMON")]
+    no_daemon: bool,
+    /// Seconds a daemon waits without clients before exiting, or 0 to wait forever
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        env = "NOEMATIC_IDLE_TIMEOUT",
+        default_value_t = 60
+    )]
+    idle_timeout: u64,
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Id
     id: Option<String>,
 }
 
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print database statistics as JSON
+    Stats,
+    /// Write a consistent copy of the database to a new file
+    Backup {
+        /// Path of the backup, which must not exist
+        path: PathBuf,
+    },
+    /// Replace the database with a backup, first backing it up into the backups directory
+    Restore {
+        /// Path of the backup
+        path: PathBuf,
+    },
+    /// Check the database and its full-text indexes, printing findings as JSON
+    Doctor {
+        /// Recreate the full-text indexes from the saved sites
+        #[arg(long)]
+        rebuild: bool,
+        /// Merge the full-text indexes and vacuum the database
+        #[arg(long)]
+        optimize: bool,
+    },
+    /// Encrypt the database with the passphrase in the key file
+    Encrypt,
+    /// Decrypt the database with the passphrase in the key file
+    Decrypt,
+    /// Serve the database to clients connecting to its socket
+    Serve,
+    /// Serve a REST API, protected by the token in the data directory
+    Http {
+        /// Address to listen on, such as 127.0.0.1:8080
+        #[arg(long, value_name = "ADDR")]
+        bind: SocketAddr,
+    },
+    /// Serve JSON-RPC 2.0 and Model Context Protocol tools on stdin and stdout
+    Rpc,
+    /// Print the JSON Schema of the messages
+    Schema {
+        /// Print TypeScript definitions instead
+        #[arg(long)]
+        typescript: bool,
+    },
+}
+
 /// Reads the length prefix of a message.
 ///
 /// Returns `None` if the reader is at EOF.
@@ -54,14 +204,76 @@ fn read_bytes(reader: &mut impl BufRead, length: u32) -> io::Result<Vec<u8>> {
     Ok(ret)
 }
 
-/// Reads a length `n`-prefixed bytestring into a vector of length `n`.
+/// A message read from a client.
+enum Inbound {
+    Message(Vec<u8>),
+    /// A message which was skipped because it was too large.
+    Oversized {
+        length: u32,
+        edges: Edges,
+    },
+}
+
+/// Keeps the first and the last [`EDGE_LENGTH`] bytes written to it, which may overlap.
+#[derive(Default)]
+struct Edges {
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl Write for Edges {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let split = EDGE_LENGTH.saturating_sub(self.head.len()).min(buf.len());
+        self.head.extend_from_slice(&buf[..split]);
+        self.tail.extend_from_slice(buf);
+        if self.tail.len() > 2 * EDGE_LENGTH {
+            self.tail.drain(..self.tail.len() - EDGE_LENGTH);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads a length `n`-prefixed bytestring into a vector of length `n`, skipping all but its
+/// edges if `n` is larger than `max_length`.
 ///
 /// Returns `None` if the reader is at EOF.
-fn read_message_bytes(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>, Error> {
+fn read_message_bytes(
+    reader: &mut impl BufRead,
+    max_length: u32,
+) -> Result<Option<Inbound>, Error> {
     let Some(length) = read_length(reader)? else {
         return Ok(None);
     };
-    read_bytes(reader, length).map(Some).map_err(Into::into)
+    if length > max_length {
+        let mut message = reader.by_ref().take(u64::from(length));
+        let mut edges = Edges::default();
+        let skipped = io::copy(&mut message, &mut edges)?;
+        if skipped < u64::from(length) {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        return Ok(Some(Inbound::Oversized { length, edges }));
+    }
+    let message_bytes = read_bytes(reader, length)?;
+    Ok(Some(Inbound::Message(message_bytes)))
+}
+
+/// Reads messages of at most `max_length` bytes until EOF, passing each to `f`, and stopping
+/// early if it returns `false`.
+fn read_messages(
+    mut reader: impl BufRead,
+    max_length: u32,
+    mut f: impl FnMut(Inbound) -> bool,
+) -> Result<(), Error> {
+    while let Some(message) = read_message_bytes(&mut reader, max_length)? {
+        if !f(message) {
+            break;
+        }
+    }
+    Ok(())
 }
 
 /// Writes a bytestring, prefixed by its length, to the writer.
@@ -80,39 +292,443 @@ fn get_project_dirs() -> Result<ProjectDirs, Error> {
         .ok_or_else(|| Error::msg(MSG_MISSING_HOME_DIR))
 }
 
-fn main() -> Result<(), Error> {
-    let args = Args::parse();
-
-    let mut context = if args.test {
-        Context::in_memory()?
-    } else {
-        let db_path = {
-            let project_dirs: ProjectDirs = get_project_dirs()?;
-            let data_dir = project_dirs.data_dir();
-            fs::create_dir_all(data_dir)?;
-            data_dir.join("db.sqlite3")
-        };
-        Context::persistent(db_path)?
+/// Writes `responses` in `encoding`, splitting JSON responses that browsers would reject as too
+/// large into chunks.
+fn write_responses(
+    writer: &mut impl Write,
+    encoding: Encoding,
+    responses: &[Response],
+) -> Result<(), Error> {
+    for response in responses {
+        if encoding != Encoding::Json {
+            write_message_bytes(writer, &encoding.encode(response)?)?;
+            continue;
+        }
+        for response_bytes in noematic::serialize_response(response, noematic::MAX_RESPONSE_SIZE)? {
+            write_message_bytes(writer, &response_bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// A message from a client, once it has been decoded.
+enum Decoded {
+    Request(Request),
+    /// The response to a message that is not handled, such as one that was too large.
+    Rejected(Response),
+}
+
+impl Decoded {
+    fn correlation_id(&self) -> &CorrelationId {
+        match self {
+            Decoded::Request(request) => &request.correlation_id,
+            Decoded::Rejected(response) => &response.correlation_id,
+        }
+    }
+}
+
+fn decode_message(
+    context: &Context,
+    encoding: Encoding,
+    message: Inbound,
+) -> Result<Decoded, Error> {
+    let message_bytes = match message {
+        Inbound::Message(message_bytes) => message_bytes,
+        Inbound::Oversized { length, edges } => {
+            let max_length = context.settings().max_request_size;
+            // Clients put the correlation id at either end of a message, usually after the
+            // payload.
+            let correlation_id = match encoding {
+                Encoding::Json => noematic::find_correlation_id(&[&edges.tail, &edges.head]),
+                Encoding::MessagePack | Encoding::Cbor => CorrelationId::new(String::new()),
+            };
+            let response = noematic::request_too_large(length, max_length, correlation_id);
+            return Ok(Decoded::Rejected(response));
+        }
     };
+    let message_json: Value = encoding.decode(&message_bytes)?;
 
-    let mut reader = BufReader::new(io::stdin());
-    let mut writer = BufWriter::new(io::stdout());
+    if let Some(response) = noematic::check_version(&message_json)? {
+        return Ok(Decoded::Rejected(response));
+    }
 
-    while let Some(message_bytes) = read_message_bytes(&mut reader)? {
-        let message_json: Value = serde_json::from_slice(&message_bytes)?;
+    match noematic::parse_request(message_json) {
+        Ok(request) => Ok(Decoded::Request(request)),
+        Err(response) => Ok(Decoded::Rejected(*response)),
+    }
+}
 
-        let version = noematic::extract_version(&message_json)?;
-        if version != MessageVersion::EXPECTED {
-            return Err(Error::msg(MSG_UNSUPPORTED_VERSION));
+/// Handles a request built by another interface, returning the actions of its responses.
+///
+/// An `errorResponse` is returned as an error holding its payload.
+fn handle(context: &mut Context, action: RequestAction) -> Result<Vec<ResponseAction>, Error> {
+    let request = Request {
+        version: MessageVersion::EXPECTED,
+        action,
+        correlation_id: CorrelationId::new(String::new()),
+    };
+    let responses = noematic::handle_request(context, &mut Session::new(), request)?;
+    let mut ret = Vec::with_capacity(responses.len());
+    for response in responses {
+        if let ResponseAction::ErrorResponse { payload } = response.action {
+            return Err(payload.into());
         }
+        ret.push(response.action);
+    }
+    Ok(ret)
+}
 
-        let request: Request = serde_json::from_value(message_json)?;
-        let responses = noematic::handle_request(&mut context, request)?;
-        for response in responses {
-            let response_bytes = serde_json::to_string(&response)?.into_bytes();
-            write_message_bytes(&mut writer, &response_bytes)?;
+/// Returns the header and the sites of the responses to a search.
+fn search_sites(
+    context: &mut Context,
+    payload: SearchRequestPayload,
+) -> Result<(SearchResponseHeaderPayload, Vec<SearchResponseSitePayload>), Error> {
+    let mut header = None;
+    let mut sites = Vec::new();
+    for action in handle(context, RequestAction::SearchRequest { payload })? {
+        match action {
+            ResponseAction::SearchResponseHeader { payload } => header = Some(payload),
+            ResponseAction::SearchResponseSite { payload } => sites.push(payload),
+            _ => {}
         }
     }
+    let header = header.ok_or_else(|| Error::msg(MSG_MISSING_HEADER))?;
+    Ok((header, sites))
+}
 
-    Ok(())
+/// Reads messages from stdin on another thread, so that the host can poll for changes while it
+/// waits for them.
+///
+/// The thread stops reading while [`QUEUE_LENGTH`] messages are waiting to be handled.
+fn spawn_reader(max_length: u32) -> mpsc::Receiver<Result<Inbound, Error>> {
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_LENGTH);
+    thread::spawn(move || {
+        let reader = BufReader::new(io::stdin());
+        if let Err(err) = read_messages(reader, max_length, |message| {
+            sender.send(Ok(message)).is_ok()
+        }) {
+            let _ = sender.send(Err(err));
+        }
+    });
+    receiver
+}
+
+/// Writes responses to stdout on another thread, so that requests are handled while the client
+/// is slow to read their responses.
+///
+/// The responses to each request are sent together, so that they are written together.
+fn spawn_writer(encoding: Encoding) -> (SyncSender<Vec<Response>>, JoinHandle<Result<(), Error>>) {
+    let (sender, receiver) = mpsc::sync_channel::<Vec<Response>>(QUEUE_LENGTH);
+    let handle = thread::spawn(move || {
+        let mut writer = BufWriter::new(io::stdout());
+        for responses in receiver {
+            write_responses(&mut writer, encoding, &responses)?;
+        }
+        Ok(())
+    });
+    (sender, handle)
+}
+
+fn send_responses(
+    writer: &SyncSender<Vec<Response>>,
+    responses: Vec<Response>,
+) -> Result<(), Error> {
+    writer
+        .send(responses)
+        .map_err(|_| Error::msg(MSG_WRITER_STOPPED))
+}
+
+type Finished = (CorrelationId, Result<(), Error>);
+
+/// The searches running on a [`SearchPool`], by correlation id.
+///
+/// A request waits for the search with the same correlation id, if there is one, so that the
+/// responses to requests with the same correlation id are written in the order of the requests.
+struct Searches {
+    in_flight: HashMap<CorrelationId, Cancel>,
+    sender: mpsc::Sender<Finished>,
+    receiver: mpsc::Receiver<Finished>,
+}
+
+impl Searches {
+    fn new() -> Searches {
+        let (sender, receiver) = mpsc::channel();
+        Searches {
+            in_flight: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    fn finish(&mut self, (correlation_id, result): Finished) -> Result<(), Error> {
+        self.in_flight.remove(&correlation_id);
+        result
+    }
+
+    /// Records the searches that have finished, waiting for the one with `correlation_id` if it
+    /// is still running.
+    fn wait_for(&mut self, correlation_id: &CorrelationId) -> Result<(), Error> {
+        while let Ok(finished) = self.receiver.try_recv() {
+            self.finish(finished)?;
+        }
+        while self.in_flight.contains_key(correlation_id) {
+            let finished = self.receiver.recv()?;
+            self.finish(finished)?;
+        }
+        Ok(())
+    }
+
+    /// Stops the search with `correlation_id`, returning whether it had yet to finish.
+    fn cancel(&self, correlation_id: &CorrelationId) -> bool {
+        self.in_flight
+            .get(correlation_id)
+            .is_some_and(Cancel::cancel)
+    }
+
+    fn wait_all(&mut self) -> Result<(), Error> {
+        while !self.in_flight.is_empty() {
+            let finished = self.receiver.recv()?;
+            self.finish(finished)?;
+        }
+        Ok(())
+    }
+
+    fn submit(
+        &mut self,
+        pool: &SearchPool,
+        request: Request,
+        writer: &SyncSender<Vec<Response>>,
+    ) -> Result<(), Error> {
+        let correlation_id = request.correlation_id.clone();
+        let key = correlation_id.clone();
+        let writer = writer.clone();
+        let sender = self.sender.clone();
+        let callback: Callback = Box::new(move |result| {
+            // The search is finished once its responses are queued to be written.
+            let result = result.and_then(|responses| send_responses(&writer, responses));
+            let _ = sender.send((correlation_id, result));
+        });
+        let cancel = pool.submit(request, callback)?;
+        self.in_flight.insert(key, cancel);
+        Ok(())
+    }
+}
+
+/// Handles the messages from `receiver`, sending searches to `pool` if there is one, and the
+/// responses to `writer`.
+fn handle_messages(
+    context: &mut Context,
+    encoding: Encoding,
+    receiver: &mpsc::Receiver<Result<Inbound, Error>>,
+    writer: &SyncSender<Vec<Response>>,
+    pool: Option<&SearchPool>,
+) -> Result<(), Error> {
+    let mut session = Session::new();
+    let mut searches = Searches::new();
+
+    loop {
+        let message = if session.is_subscribed() {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    send_responses(writer, context.poll_changes(&mut session)?)?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => message,
+                Err(RecvError) => break,
+            }
+        };
+        let decoded = decode_message(context, encoding, message?)?;
+        // A search is cancelled without waiting for it, even if it has the same correlation id.
+        if let Decoded::Request(request) = &decoded
+            && let Some(response) = noematic::cancel(request, |id| searches.cancel(id))
+        {
+            send_responses(writer, vec![response])?;
+            continue;
+        }
+        searches.wait_for(decoded.correlation_id())?;
+        match decoded {
+            Decoded::Rejected(response) => send_responses(writer, vec![response])?,
+            Decoded::Request(request) => match pool {
+                Some(pool) if SearchPool::accepts(&request) => {
+                    searches.submit(pool, request, writer)?;
+                }
+                _ => {
+                    let responses = noematic::handle_request(context, &mut session, request)?;
+                    send_responses(writer, responses)?;
+                }
+            },
+        }
+    }
+
+    searches.wait_all()
+}
+
+/// Handles messages from stdin, writing their responses to stdout.
+///
+/// Messages are read, handled and written on separate threads, and searches of a database file
+/// are handled by a [`SearchPool`], so that a slow search does not hold up the requests after it.
+fn run(context: &mut Context, encoding: Encoding) -> Result<(), Error> {
+    let receiver = spawn_reader(context.settings().max_request_size);
+    let (writer, writer_thread) = spawn_writer(encoding);
+    let pool = SearchPool::new(context, POOL_SIZE)?;
+    let result = handle_messages(context, encoding, &receiver, &writer, pool.as_ref());
+    drop(writer);
+    let written = writer_thread
+        .join()
+        .map_err(|_| Error::msg(MSG_WRITER_STOPPED))?;
+    // A failure to write explains a failure to send responses to the writer.
+    written.and(result)
+}
+
+/// Parses the command line, ignoring the environment with --test so that tests do not depend on
+/// it.
+fn parse_args() -> Args {
+    // The environment is only read once --test is known to be absent, so that a value in it
+    // cannot stop a test run, even if it is invalid.
+    let without_env = Args::command().mut_args(|arg| arg.env(None));
+    if let Ok(matches) = without_env.try_get_matches()
+        && let Ok(args) = Args::from_arg_matches(&matches)
+        && args.test
+    {
+        return args;
+    }
+    Args::parse()
+}
+
+/// Combines the defaults, the config file, the environment and the command line, in increasing
+/// order of precedence.
+///
+/// With --test, only the defaults and the command line are combined, along with a config file
+/// given by --config.
+fn get_settings(args: &Args) -> Result<Settings, Error> {
+    let overrides = Overrides {
+        db: args.db.clone(),
+        key_file: args.key_file.clone(),
+        profile: args.profile.clone(),
+        trash_retention_days: args.trash_retention_days,
+        query_regex: args.query_regex.clone(),
+        snippet_tokens: args.snippet_tokens,
+        max_page_length: args.max_page_length,
+        max_request_size: args.max_request_size,
+    };
+    if args.test {
+        return Settings::in_memory(args.config.as_deref(), overrides);
+    }
+    let project_dirs: ProjectDirs = get_project_dirs()?;
+    let config_path = match &args.config {
+        Some(config_path) => config_path.clone(),
+        None => project_dirs.config_dir().join(CONFIG_FILE),
+    };
+    let settings = Settings::resolve(&config_path, overrides, project_dirs.data_dir())?;
+    if let Some(parent) = settings.db.as_deref().and_then(Path::parent) {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(settings)
+}
+
+fn main() -> Result<(), Error> {
+    let args = parse_args();
+    if let Some(Command::Schema { typescript }) = args.command {
+        if typescript {
+            print!("{}", noematic::schema::typescript());
+        } else {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&noematic::schema::json_schema())?
+            );
+        }
+        return Ok(());
+    }
+    let settings = get_settings(&args)?;
+
+    match args.command {
+        Some(Command::Restore { path }) => {
+            let Some(db_path) = &settings.db else {
+                return Err(Error::msg(MSG_IN_MEMORY));
+            };
+            #[cfg(unix)]
+            daemon::ensure_not_running(&settings)?;
+            noematic::restore(&settings, db_path, path)
+        }
+        Some(Command::Encrypt) => {
+            let Some(db_path) = &settings.db else {
+                return Err(Error::msg(MSG_IN_MEMORY));
+            };
+            #[cfg(unix)]
+            daemon::ensure_not_running(&settings)?;
+            noematic::encrypt(&settings, db_path)
+        }
+        Some(Command::Decrypt) => {
+            let Some(db_path) = &settings.db else {
+                return Err(Error::msg(MSG_IN_MEMORY));
+            };
+            #[cfg(unix)]
+            daemon::ensure_not_running(&settings)?;
+            noematic::decrypt(&settings, db_path)
+        }
+        Some(Command::Backup { path }) => {
+            let context = Context::new(settings)?;
+            context.backup(path)
+        }
+        Some(Command::Doctor { rebuild, optimize }) => {
+            let context = Context::new(settings)?;
+            let report = context.maintain(&MaintenanceRequestPayload { rebuild, optimize })?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            let findings = report.after.as_ref().unwrap_or(&report.before);
+            if !findings.healthy {
+                return Err(Error::msg(MSG_UNHEALTHY));
+            }
+            Ok(())
+        }
+        Some(Command::Stats) => {
+            let context = Context::new(settings)?;
+            let stats = context.stats()?;
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            Ok(())
+        }
+        #[cfg(unix)]
+        Some(Command::Serve) => {
+            let Some(socket) = settings.socket() else {
+                return Err(Error::msg(MSG_IN_MEMORY));
+            };
+            let listener = daemon::bind(&socket)?;
+            let mut context = Context::new(settings)?;
+            context.purge_trash()?;
+            let idle_timeout =
+                (args.idle_timeout > 0).then(|| Duration::from_secs(args.idle_timeout));
+            daemon::serve(&mut context, listener, &socket, idle_timeout)
+        }
+        #[cfg(not(unix))]
+        Some(Command::Serve) => Err(Error::msg(MSG_NO_DAEMON)),
+        Some(Command::Http { bind }) => {
+            let token = http::token(get_project_dirs()?.data_dir())?;
+            let mut context = Context::new(settings)?;
+            context.purge_trash()?;
+            http::serve(&mut context, bind, &token)
+        }
+        Some(Command::Rpc) => {
+            let mut context = Context::new(settings)?;
+            context.purge_trash()?;
+            rpc::serve(&mut context)
+        }
+        Some(Command::Schema { .. }) => unreachable!("handled before the settings are resolved"),
+        None => {
+            // The daemon only speaks JSON, which browsers require.
+            #[cfg(unix)]
+            if let Some(socket) = settings.socket()
+                && !args.no_daemon
+                && args.encoding == Encoding::Json
+            {
+                return daemon::proxy(&settings, &socket, args.idle_timeout);
+            }
+            let mut context = Context::new(settings)?;
+            context.purge_trash()?;
+            run(&mut context, args.encoding)
+        }
+    }
 }
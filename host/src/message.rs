@@ -1,7 +1,12 @@
-use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use std::path::PathBuf;
+
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+use crate::config::Settings;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct MessageVersion(semver::Version);
 
 impl MessageVersion {
@@ -19,6 +24,59 @@ impl MessageVersion {
     }
 
     pub const EXPECTED: MessageVersion = MessageVersion::new(0, 1, 0);
+
+    /// Returns whether messages of this version have the same shape as those of `other`, which
+    /// is the case if their major versions, or before 1.0.0 their minor versions, are equal.
+    #[must_use]
+    pub fn is_compatible(&self, other: &MessageVersion) -> bool {
+        let (this, other) = (&self.0, &other.0);
+        this.major == other.major && (this.major != 0 || this.minor == other.minor)
+    }
+}
+
+/// An inclusive range of message versions.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VersionRange {
+    pub min: MessageVersion,
+    pub max: MessageVersion,
+}
+
+impl VersionRange {
+    /// The versions this host accepts.
+    pub const SUPPORTED: VersionRange = VersionRange {
+        min: MessageVersion::new(0, 1, 0),
+        max: MessageVersion::EXPECTED,
+    };
+
+    /// Returns whether this host accepts requests of `version`, which may be a newer but
+    /// compatible version than [`MessageVersion::EXPECTED`].
+    #[must_use]
+    pub fn accepts(&self, version: &MessageVersion) -> bool {
+        self.min <= *version && version.is_compatible(&self.max)
+    }
+
+    /// Returns the latest version in this range that the host accepts, if there is one.
+    ///
+    /// That is the end of the range if the host accepts it, or else [`MessageVersion::EXPECTED`]
+    /// if the range contains it.
+    #[must_use]
+    pub fn negotiate(&self) -> Option<MessageVersion> {
+        if self.min > self.max {
+            return None;
+        }
+        if VersionRange::SUPPORTED.accepts(&self.max) {
+            return Some(self.max.clone());
+        }
+        let expected = MessageVersion::EXPECTED;
+        (self.min <= expected && expected <= self.max).then_some(expected)
+    }
+}
+
+impl std::fmt::Display for MessageVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 impl From<semver::Version> for MessageVersion {
@@ -29,7 +87,7 @@ impl From<semver::Version> for MessageVersion {
 
 macro_rules! wrap_string {
     ($name:ident) => {
-        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+        #[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
         pub struct $name(String);
 
         impl $name {
@@ -76,40 +134,434 @@ wrap_string!(CorrelationId);
 wrap_string!(Url);
 wrap_string!(Title);
 wrap_string!(InnerText);
-wrap_string!(Snippet);
 wrap_string!(Query);
+wrap_string!(Note);
+wrap_string!(Excerpt);
+
+/// Text of a site around the matches of a search, which are wrapped in `<b>` tags.
+//
+// Within the host, matches are instead between `MATCH_START` and `MATCH_END`, so that they can be
+// told apart from tags in the text.
+#[derive(JsonSchema, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Snippet(String);
+
+impl Snippet {
+    /// Marks the start of a match.
+    pub const MATCH_START: char = '\u{2}';
+
+    /// Marks the end of a match.
+    pub const MATCH_END: char = '\u{3}';
+
+    /// Returns the text, with each match between [`Snippet::MATCH_START`] and
+    /// [`Snippet::MATCH_END`].
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Returns the text, with each match wrapped in `<b>` tags.
+    #[must_use]
+    pub fn to_tagged(&self) -> String {
+        self.0
+            .replace(Snippet::MATCH_START, "<b>")
+            .replace(Snippet::MATCH_END, "</b>")
+    }
+}
+
+impl Serialize for Snippet {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_tagged())
+    }
+}
 
-#[derive(Serialize, Deserialize, Debug)]
+impl<'de> Deserialize<'de> for Snippet {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Snippet, D::Error> {
+        let tagged = String::deserialize(deserializer)?;
+        let text = tagged
+            .replace("<b>", &Snippet::MATCH_START.to_string())
+            .replace("</b>", &Snippet::MATCH_END.to_string());
+        Ok(Snippet(text))
+    }
+}
+
+impl FromSql for Snippet {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        String::column_result(value).map(Snippet)
+    }
+}
+wrap_string!(Timestamp);
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnotationId(i64);
+
+impl AnnotationId {
+    #[must_use]
+    pub const fn new(value: i64) -> AnnotationId {
+        AnnotationId(value)
+    }
+}
+
+impl ToSql for AnnotationId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for AnnotationId {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(AnnotationId)
+    }
+}
+
+/// Identifies a save whose text is sent in chunks.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UploadId(i64);
+
+impl UploadId {
+    #[must_use]
+    pub const fn new(value: i64) -> UploadId {
+        UploadId(value)
+    }
+}
+
+impl std::fmt::Display for UploadId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ToSql for UploadId {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.0.to_sql()
+    }
+}
+
+impl FromSql for UploadId {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        i64::column_result(value).map(UploadId)
+    }
+}
+
+/// Where a saved site is in the user's backlog.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
+pub enum Status {
+    Unread,
+    Read,
+    Archived,
+}
+
+impl Status {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Status::Unread => "unread",
+            Status::Read => "read",
+            Status::Archived => "archived",
+        }
+    }
+}
+
+impl ToSql for Status {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.as_str().to_sql()
+    }
+}
+
+impl FromSql for Status {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "unread" => Ok(Status::Unread),
+            "read" => Ok(Status::Read),
+            "archived" => Ok(Status::Archived),
+            other => Err(FromSqlError::Other(
+                format!("Invalid status: {other}").into(),
+            )),
+        }
+    }
+}
+
+/// How a site was changed, as reported to subscribers.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Change {
+    Saved,
+    Removed,
+    Restored,
+    Deleted,
+    StatusChanged,
+    Annotated,
+}
+
+impl FromSql for Change {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "saved" => Ok(Change::Saved),
+            "removed" => Ok(Change::Removed),
+            "restored" => Ok(Change::Restored),
+            "deleted" => Ok(Change::Deleted),
+            "statusChanged" => Ok(Change::StatusChanged),
+            "annotated" => Ok(Change::Annotated),
+            other => Err(FromSqlError::Other(
+                format!("Invalid change: {other}").into(),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct SaveRequestPayload {
     pub url: Url,
     pub title: Title,
     pub inner_text: InnerText,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct RemoveRequestPayload {
     pub url: Url,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct GetRequestPayload {
+    pub url: Url,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RestoreRequestPayload {
+    pub url: Url,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct EmptyTrashRequestPayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StatsRequestPayload {}
+
+/// `path` must be absolute, and must not name an existing file.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BackupRequestPayload {
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigRequestPayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SubscribeRequestPayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CapabilitiesRequestPayload {}
+
+/// Starts a save of a site whose text is too large for one message.
+///
+/// The text is sent in `saveChunkRequest`s, and the site is saved by a `saveCommitRequest`, which
+/// only the client that started the save may send.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SaveBeginRequestPayload {
+    pub url: Url,
+    pub title: Title,
+}
+
+/// `index` counts from 0. A chunk sent again replaces the one with the same index.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SaveChunkRequestPayload {
+    pub upload_id: UploadId,
+    pub index: usize,
+    pub text: String,
+}
+
+/// Saves the site with the text of chunks `0` to `count - 1`, joined in order.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SaveCommitRequestPayload {
+    pub upload_id: UploadId,
+    pub count: usize,
+}
+
+/// `capabilities` are the optional features of the protocol that the client supports. They are
+/// accepted so that clients can offer them, but the host does not yet depend on any.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HelloRequestPayload {
+    pub versions: VersionRange,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceRequestPayload {
+    /// Recreate the full-text indexes from the saved sites.
+    #[serde(default)]
+    pub rebuild: bool,
+    /// Merge the full-text indexes and `VACUUM` the database.
+    #[serde(default)]
+    pub optimize: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct SearchRequestPayload {
     pub query: Query,
     pub page_num: usize,
     pub page_length: usize,
+    pub status: Option<Status>,
+}
+
+/// `correlationId` is that of the search to cancel, rather than of the `cancelRequest`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CancelRequestPayload {
+    pub correlation_id: CorrelationId,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SetStatusRequestPayload {
+    pub url: Url,
+    pub status: Status,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ListRequestPayload {
+    pub page_num: usize,
+    pub page_length: usize,
+    pub status: Option<Status>,
+    /// List the sites in the trash instead of the saved ones.
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+/// A selection of a site's `inner_text`, delimited by offsets in UTF-16 code units, as JavaScript
+/// indexes strings.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Quote {
+    pub text: Excerpt,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AddAnnotationRequestPayload {
+    pub url: Url,
+    pub note: Note,
+    pub quote: Option<Quote>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct EditAnnotationRequestPayload {
+    pub id: AnnotationId,
+    pub note: Note,
+    pub quote: Option<Quote>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RemoveAnnotationRequestPayload {
+    pub id: AnnotationId,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ListAnnotationsRequestPayload {
+    pub url: Url,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(tag = "action", rename_all = "camelCase")]
 pub enum RequestAction {
-    SaveRequest { payload: SaveRequestPayload },
-    RemoveRequest { payload: RemoveRequestPayload },
-    SearchRequest { payload: SearchRequestPayload },
+    SaveRequest {
+        payload: SaveRequestPayload,
+    },
+    RemoveRequest {
+        payload: RemoveRequestPayload,
+    },
+    SearchRequest {
+        payload: SearchRequestPayload,
+    },
+    AddAnnotationRequest {
+        payload: AddAnnotationRequestPayload,
+    },
+    EditAnnotationRequest {
+        payload: EditAnnotationRequestPayload,
+    },
+    RemoveAnnotationRequest {
+        payload: RemoveAnnotationRequestPayload,
+    },
+    ListAnnotationsRequest {
+        payload: ListAnnotationsRequestPayload,
+    },
+    SetStatusRequest {
+        payload: SetStatusRequestPayload,
+    },
+    ListRequest {
+        payload: ListRequestPayload,
+    },
+    RestoreRequest {
+        payload: RestoreRequestPayload,
+    },
+    EmptyTrashRequest {
+        payload: EmptyTrashRequestPayload,
+    },
+    StatsRequest {
+        payload: StatsRequestPayload,
+    },
+    BackupRequest {
+        payload: BackupRequestPayload,
+    },
+    MaintenanceRequest {
+        payload: MaintenanceRequestPayload,
+    },
+    ConfigRequest {
+        payload: ConfigRequestPayload,
+    },
+    SubscribeRequest {
+        payload: SubscribeRequestPayload,
+    },
+    GetRequest {
+        payload: GetRequestPayload,
+    },
+    /// Accepted whatever its version, so that clients can find a version to use.
+    HelloRequest {
+        payload: HelloRequestPayload,
+    },
+    CapabilitiesRequest {
+        payload: CapabilitiesRequestPayload,
+    },
+    SaveBeginRequest {
+        payload: SaveBeginRequestPayload,
+    },
+    SaveChunkRequest {
+        payload: SaveChunkRequestPayload,
+    },
+    /// Answered by a `saveResponse`.
+    SaveCommitRequest {
+        payload: SaveCommitRequestPayload,
+    },
+    /// Stops a search that is running or waiting to run. It is not held up by the search, even
+    /// if they have the same correlation id.
+    CancelRequest {
+        payload: CancelRequestPayload,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Request {
     pub version: MessageVersion,
@@ -118,13 +570,13 @@ pub struct Request {
     pub correlation_id: CorrelationId,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct SaveResponsePayload {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct RemoveResponsePayload {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResponseHeaderPayload {
     pub query: Query,
@@ -133,15 +585,313 @@ pub struct SearchResponseHeaderPayload {
     pub has_more: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Offsets of a passage within a site's `inner_text`, in UTF-16 code units, as JavaScript indexes
+/// strings.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassageRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResponseSitePayload {
     pub url: Url,
     pub title: Title,
     pub snippet: Snippet,
+    pub passage: Option<PassageRange>,
+    pub status: Status,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: AnnotationId,
+    pub url: Url,
+    pub note: Note,
+    pub quote: Option<Quote>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+/// `annotation` is `None` if there is no saved site with the given url.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct AddAnnotationResponsePayload {
+    pub annotation: Option<Annotation>,
+}
+
+/// `annotation` is `None` if there is no annotation with the given id.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct EditAnnotationResponsePayload {
+    pub annotation: Option<Annotation>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RemoveAnnotationResponsePayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ListAnnotationsResponsePayload {
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SetStatusResponsePayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponseHeaderPayload {
+    pub page_num: usize,
+    pub page_length: usize,
+    pub has_more: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponseSitePayload {
+    pub url: Url,
+    pub title: Title,
+    pub status: Status,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub status_updated_at: Option<Timestamp>,
+}
+
+/// A saved site, with its text.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Site {
+    pub url: Url,
+    pub title: Title,
+    pub inner_text: InnerText,
+    pub status: Status,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub status_updated_at: Option<Timestamp>,
+}
+
+/// `site` is `None` if there is no saved site with the given url.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct GetResponsePayload {
+    pub site: Option<Site>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct RestoreResponsePayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct EmptyTrashResponsePayload {
+    pub removed: usize,
+}
+
+/// The number of saved sites in each [`Status`], excluding those in the trash.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Default)]
+pub struct StatusCounts {
+    pub unread: usize,
+    pub read: usize,
+    pub archived: usize,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct DomainCount {
+    pub domain: String,
+    pub count: usize,
+}
+
+/// Sizes are in bytes.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsResponsePayload {
+    pub total_sites: usize,
+    pub status_counts: StatusCounts,
+    pub trashed_sites: usize,
+    pub total_text_bytes: u64,
+    pub database_size: u64,
+    pub fts_index_size: u64,
+    pub oldest_created_at: Option<Timestamp>,
+    pub newest_created_at: Option<Timestamp>,
+    pub top_domains: Vec<DomainCount>,
+    pub schema_version: semver::Version,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct BackupResponsePayload {}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct FtsProblem {
+    pub table: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceFindings {
+    /// Problems reported by `PRAGMA integrity_check`.
+    pub integrity_check: Vec<String>,
+    /// Problems reported by the FTS5 `integrity-check` command.
+    pub fts_integrity_check: Vec<FtsProblem>,
+    /// The number of rows in `sites`.
+    pub sites: usize,
+    /// The number of rows in `sites_fts`.
+    pub indexed_sites: usize,
+    /// The number of sites missing from `sites_fts`.
+    pub unindexed_sites: usize,
+    /// The number of sites with text but no passages.
+    pub sites_without_passages: usize,
+    pub healthy: bool,
+}
+
+/// `after` is present if any repairs were requested, and describes the database after they ran.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceResponsePayload {
+    pub before: MaintenanceFindings,
+    pub after: Option<MaintenanceFindings>,
+    pub rebuilt: bool,
+    pub optimized: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SubscribeResponsePayload {}
+
+/// `version` is the version the client should use, and `versions` and `capabilities` are those
+/// of the host.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct HelloResponsePayload {
+    pub version: MessageVersion,
+    pub versions: VersionRange,
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveBeginResponsePayload {
+    pub upload_id: UploadId,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct SaveChunkResponsePayload {}
+
+/// What became of the search that a `cancelRequest` was for.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CancelStatus {
+    /// The search was stopped, and sends no more responses.
+    Cancelled,
+    /// There is no search with the correlation id that has yet to finish, so all of its
+    /// responses are sent.
+    NotRunning,
+}
+
+/// `correlationId` is that of the search.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelResponsePayload {
+    pub correlation_id: CorrelationId,
+    pub status: CancelStatus,
+}
+
+/// Sizes are in bytes.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    pub max_request_size: u32,
+    pub max_response_size: usize,
+    pub max_page_length: usize,
+}
+
+/// What this build of the host supports, so that clients can adapt to it.
+///
+/// `features` are the optional features the host was built with, such as `encryption`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesResponsePayload {
+    pub host_version: String,
+    pub schema_version: semver::Version,
+    pub request_actions: Vec<String>,
+    pub fts_tokenizer: String,
+    pub features: Vec<String>,
+    pub limits: Limits,
+}
+
+/// A field of a request whose value is not accepted.
+///
+/// `field` is the name of the field in the request's payload.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// A request that was understood, but could not be handled.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ErrorResponsePayload {
+    /// The request's version, or every version offered in a `helloRequest`, is not supported.
+    IncompatibleVersion {
+        message: String,
+        supported: VersionRange,
+    },
+    /// The request was larger than the host reads, and was skipped. Its correlation id is found
+    /// at either end of a JSON request, and is otherwise empty.
+    #[serde(rename_all = "camelCase")]
+    RequestTooLarge {
+        message: String,
+        size: u32,
+        max_size: u32,
+    },
+    /// There is no upload with the id that the client started, or some of its chunks are
+    /// missing. Uploads that are not committed are discarded after an hour.
+    #[serde(rename_all = "camelCase")]
+    InvalidUpload {
+        message: String,
+        upload_id: UploadId,
+    },
+    /// The request could not be parsed, or some of its fields are invalid. `fields` is empty if
+    /// it could not be parsed, in which case `message` says why.
+    InvalidRequest {
+        message: String,
+        fields: Vec<FieldError>,
+    },
+}
+
+impl ErrorResponsePayload {
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            ErrorResponsePayload::IncompatibleVersion { message, .. }
+            | ErrorResponsePayload::RequestTooLarge { message, .. }
+            | ErrorResponsePayload::InvalidUpload { message, .. }
+            | ErrorResponsePayload::InvalidRequest { message, .. } => message,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorResponsePayload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ErrorResponsePayload {}
+
+/// One part of a response too large to send in one message.
+///
+/// The `data` of the `count` chunks, in order of `index`, joins to give the JSON of the response.
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ResponseChunkPayload {
+    pub index: usize,
+    pub count: usize,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteChangedPayload {
+    pub url: Url,
+    pub change: Change,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(tag = "action", rename_all = "camelCase")]
 pub enum ResponseAction {
     SaveResponse {
@@ -156,9 +906,79 @@ pub enum ResponseAction {
     SearchResponseSite {
         payload: SearchResponseSitePayload,
     },
+    AddAnnotationResponse {
+        payload: AddAnnotationResponsePayload,
+    },
+    EditAnnotationResponse {
+        payload: EditAnnotationResponsePayload,
+    },
+    RemoveAnnotationResponse {
+        payload: RemoveAnnotationResponsePayload,
+    },
+    ListAnnotationsResponse {
+        payload: ListAnnotationsResponsePayload,
+    },
+    SetStatusResponse {
+        payload: SetStatusResponsePayload,
+    },
+    ListResponseHeader {
+        payload: ListResponseHeaderPayload,
+    },
+    ListResponseSite {
+        payload: ListResponseSitePayload,
+    },
+    RestoreResponse {
+        payload: RestoreResponsePayload,
+    },
+    EmptyTrashResponse {
+        payload: EmptyTrashResponsePayload,
+    },
+    StatsResponse {
+        payload: StatsResponsePayload,
+    },
+    BackupResponse {
+        payload: BackupResponsePayload,
+    },
+    MaintenanceResponse {
+        payload: MaintenanceResponsePayload,
+    },
+    ConfigResponse {
+        payload: Settings,
+    },
+    SubscribeResponse {
+        payload: SubscribeResponsePayload,
+    },
+    GetResponse {
+        payload: GetResponsePayload,
+    },
+    HelloResponse {
+        payload: HelloResponsePayload,
+    },
+    CapabilitiesResponse {
+        payload: CapabilitiesResponsePayload,
+    },
+    ErrorResponse {
+        payload: ErrorResponsePayload,
+    },
+    ResponseChunk {
+        payload: ResponseChunkPayload,
+    },
+    SaveBeginResponse {
+        payload: SaveBeginResponsePayload,
+    },
+    SaveChunkResponse {
+        payload: SaveChunkResponsePayload,
+    },
+    CancelResponse {
+        payload: CancelResponsePayload,
+    },
+    /// Sent without a request to subscribers, after a site is changed by any host process.
+    SiteChanged {
+        payload: SiteChangedPayload,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Response {
     pub version: MessageVersion,
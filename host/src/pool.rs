@@ -0,0 +1,138 @@
+//! Searches handled on other threads, so that a slow search does not hold up the requests after
+//! it.
+//!
+//! Each thread has a read-only connection, while the connection of the [`Context`] remains the
+//! only one that writes.
+
+use std::{
+    sync::{
+        Arc, Mutex, PoisonError,
+        mpsc::{self, SyncSender},
+    },
+    thread,
+};
+
+use anyhow::Error;
+use noematic::{
+    Context, Searcher,
+    message::{Request, RequestAction, Response},
+};
+
+const MSG_POOL_STOPPED: &str = "Search threads stopped";
+
+/// The number of searches that can run at once.
+pub const POOL_SIZE: usize = 4;
+
+/// The number of searches that can wait for a thread before [`SearchPool::submit`] blocks.
+const QUEUE_LENGTH: usize = 16;
+
+/// Called with the responses to a search, on the thread that handled it.
+pub type Callback = Box<dyn FnOnce(Result<Vec<Response>, Error>) + Send>;
+
+enum State {
+    Queued,
+    Running,
+    Cancelled,
+    Finished,
+}
+
+/// Stops a search submitted to a [`SearchPool`].
+pub struct Cancel(Arc<Mutex<State>>);
+
+impl Cancel {
+    /// Stops the search, which is checked for while it runs, so that its callback is called with
+    /// no responses. Returns whether the search had yet to finish.
+    pub fn cancel(&self) -> bool {
+        let mut state = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if let State::Finished = *state {
+            return false;
+        }
+        *state = State::Cancelled;
+        true
+    }
+}
+
+struct Job {
+    request: Request,
+    callback: Callback,
+    state: Arc<Mutex<State>>,
+}
+
+impl Job {
+    fn run(self, searcher: &Searcher) {
+        {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if let State::Cancelled = *state {
+                drop(state);
+                (self.callback)(Ok(Vec::new()));
+                return;
+            }
+            *state = State::Running;
+        }
+        let state = Arc::clone(&self.state);
+        let result = searcher.handle_cancellable_request(self.request, move || {
+            let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            matches!(*state, State::Cancelled)
+        });
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let cancelled = matches!(*state, State::Cancelled);
+        *state = State::Finished;
+        drop(state);
+        // A cancelled search may have finished before it was stopped, but its responses are not
+        // sent either way.
+        (self.callback)(if cancelled { Ok(Vec::new()) } else { result });
+    }
+}
+
+pub struct SearchPool {
+    sender: SyncSender<Job>,
+}
+
+impl SearchPool {
+    /// Starts `size` threads searching the database of `context`, or returns `None` if it is in
+    /// memory.
+    pub fn new(context: &Context, size: usize) -> Result<Option<SearchPool>, Error> {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(QUEUE_LENGTH);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..size {
+            let Some(searcher) = context.searcher()? else {
+                return Ok(None);
+            };
+            let receiver = Arc::clone(&receiver);
+            thread::spawn(move || {
+                loop {
+                    // The lock is only held while waiting for a job, not while handling it.
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+                    let Ok(job) = job else {
+                        break;
+                    };
+                    job.run(&searcher);
+                }
+            });
+        }
+        Ok(Some(SearchPool { sender }))
+    }
+
+    /// Returns whether `request` can be handled by the pool.
+    pub fn accepts(request: &Request) -> bool {
+        matches!(request.action, RequestAction::SearchRequest { .. })
+    }
+
+    /// Queues a search, which must be [accepted](SearchPool::accepts), blocking while the queue is
+    /// full.
+    pub fn submit(&self, request: Request, callback: Callback) -> Result<Cancel, Error> {
+        let state = Arc::new(Mutex::new(State::Queued));
+        let job = Job {
+            request,
+            callback,
+            state: Arc::clone(&state),
+        };
+        self.sender
+            .send(job)
+            .map_err(|_| Error::msg(MSG_POOL_STOPPED))?;
+        Ok(Cancel(state))
+    }
+}
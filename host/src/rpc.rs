@@ -0,0 +1,312 @@
+//! A JSON-RPC 2.0 server on stdin and stdout, with one message on each line.
+//!
+//! The methods `search`, `get`, `save` and `remove` can be called directly, or as tools of the
+//! Model Context Protocol, which also uses `initialize`, `tools/list` and `tools/call`.
+
+use std::io::{self, BufRead, Write};
+
+use anyhow::Error;
+use noematic::{
+    Context,
+    config::Settings,
+    message::{
+        ErrorResponsePayload, GetRequestPayload, Query, RemoveRequestPayload, RequestAction,
+        ResponseAction, SaveRequestPayload, SearchRequestPayload, Status,
+    },
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+
+use crate::{handle, search_sites};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// The versions of the Model Context Protocol that are supported, latest first.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// The page length of searches which do not give one, unless the largest allowed is smaller.
+const DEFAULT_PAGE_LENGTH: usize = 10;
+
+const MSG_INVALID_REQUEST: &str = "Invalid request";
+const MSG_METHOD_NOT_FOUND: &str = "Method not found";
+const MSG_UNKNOWN_TOOL: &str = "Unknown tool";
+const MSG_UNEXPECTED_RESPONSE: &str = "Unexpected response";
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> RpcError {
+        let message = message.into();
+        RpcError { code, message }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(err: Error) -> RpcError {
+        // An `errorResponse`, such as one listing invalid fields, is the client's to fix.
+        let code = if err.is::<ErrorResponsePayload>() {
+            INVALID_PARAMS
+        } else {
+            INTERNAL_ERROR
+        };
+        RpcError::new(code, format!("{err:#}"))
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchParams {
+    query: Query,
+    #[serde(default)]
+    page_num: usize,
+    page_length: Option<usize>,
+    status: Option<Status>,
+}
+
+fn default_page_length(settings: &Settings) -> usize {
+    DEFAULT_PAGE_LENGTH.min(settings.max_page_length)
+}
+
+fn search(context: &mut Context, params: Value) -> Result<Value, RpcError> {
+    let params: SearchParams = parse_params(params)?;
+    let page_length = params
+        .page_length
+        .unwrap_or_else(|| default_page_length(context.settings()));
+    let payload = SearchRequestPayload {
+        query: params.query,
+        page_num: params.page_num,
+        page_length,
+        status: params.status,
+    };
+    let (header, sites) = search_sites(context, payload)?;
+    let mut ret = serde_json::to_value(header).map_err(Error::from)?;
+    ret["sites"] = serde_json::to_value(sites).map_err(Error::from)?;
+    Ok(ret)
+}
+
+fn get(context: &mut Context, params: Value) -> Result<Value, RpcError> {
+    let payload: GetRequestPayload = parse_params(params)?;
+    let actions = handle(context, RequestAction::GetRequest { payload })?;
+    match actions.into_iter().next() {
+        Some(ResponseAction::GetResponse { payload }) => {
+            Ok(serde_json::to_value(payload.site).map_err(Error::from)?)
+        }
+        _ => Err(Error::msg(MSG_UNEXPECTED_RESPONSE).into()),
+    }
+}
+
+fn save(context: &mut Context, params: Value) -> Result<Value, RpcError> {
+    let payload: SaveRequestPayload = parse_params(params)?;
+    handle(context, RequestAction::SaveRequest { payload })?;
+    Ok(json!({}))
+}
+
+fn remove(context: &mut Context, params: Value) -> Result<Value, RpcError> {
+    let payload: RemoveRequestPayload = parse_params(params)?;
+    handle(context, RequestAction::RemoveRequest { payload })?;
+    Ok(json!({}))
+}
+
+/// Calls one of the methods which are also tools, returning `None` if there is no such method.
+fn call_method(
+    context: &mut Context,
+    method: &str,
+    params: Value,
+) -> Option<Result<Value, RpcError>> {
+    let ret = match method {
+        "search" => search(context, params),
+        "get" => get(context, params),
+        "save" => save(context, params),
+        "remove" => remove(context, params),
+        _ => return None,
+    };
+    Some(ret)
+}
+
+fn tools(settings: &Settings) -> Value {
+    let url = json!({ "type": "string", "description": "The URL of the page" });
+    let page_length = json!({
+        "type": "integer",
+        "minimum": 1,
+        "maximum": settings.max_page_length,
+        "default": default_page_length(settings)
+    });
+    json!([
+        {
+            "name": "search",
+            "description": "Search the full text of saved pages. Returns matching pages with snippets, in which matches are wrapped in <b> tags.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "Words to search for" },
+                    "pageNum": { "type": "integer", "minimum": 0, "default": 0 },
+                    "pageLength": page_length,
+                    "status": { "type": "string", "enum": ["unread", "read", "archived"] }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "get",
+            "description": "Get a saved page, with its full text. Returns null if the page is not saved.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "url": url },
+                "required": ["url"]
+            }
+        },
+        {
+            "name": "save",
+            "description": "Save a page, or update a saved page.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "url": url,
+                    "title": { "type": "string" },
+                    "innerText": { "type": "string", "description": "The text of the page" }
+                },
+                "required": ["url", "title", "innerText"]
+            }
+        },
+        {
+            "name": "remove",
+            "description": "Move a saved page to the trash.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "url": url },
+                "required": ["url"]
+            }
+        }
+    ])
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params["protocolVersion"].as_str();
+    let protocol_version = PROTOCOL_VERSIONS
+        .into_iter()
+        .find(|version| Some(*version) == requested)
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": protocol_version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "noematic", "version": env!("CARGO_PKG_VERSION") }
+    })
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Option<Value>,
+}
+
+/// Calls a tool, reporting its errors in the result as the Model Context Protocol requires.
+fn call_tool(context: &mut Context, params: Value) -> Result<Value, RpcError> {
+    let call: ToolCall = parse_params(params)?;
+    let arguments = call.arguments.unwrap_or_else(|| json!({}));
+    let (text, is_error) = match call_method(context, &call.name, arguments) {
+        None => {
+            let message = format!("{MSG_UNKNOWN_TOOL}: {}", call.name);
+            return Err(RpcError::new(INVALID_PARAMS, message));
+        }
+        Some(Ok(value)) => (value.to_string(), false),
+        Some(Err(err)) => (err.message, true),
+    };
+    Ok(json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error
+    }))
+}
+
+fn call(context: &mut Context, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools(context.settings()) })),
+        "tools/call" => call_tool(context, params),
+        method => call_method(context, method, params)
+            .unwrap_or_else(|| Err(RpcError::new(METHOD_NOT_FOUND, MSG_METHOD_NOT_FOUND))),
+    }
+}
+
+fn error_response(id: &Value, err: &RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": err.code, "message": err.message }
+    })
+}
+
+/// Returns the response to `message`, or `None` if it is a notification.
+fn respond(context: &mut Context, mut message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let method = message["method"].as_str().map(str::to_string);
+    let (Some(method), Some("2.0")) = (method, message["jsonrpc"].as_str()) else {
+        let err = RpcError::new(INVALID_REQUEST, MSG_INVALID_REQUEST);
+        return Some(error_response(id.as_ref().unwrap_or(&Value::Null), &err));
+    };
+    let params = message.get_mut("params").map_or(Value::Null, Value::take);
+    let result = call(context, &method, params);
+    let id = id?;
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => error_response(&id, &err),
+    };
+    Some(response)
+}
+
+/// Returns the response to a line, which holds a message or a batch of them.
+fn respond_line(context: &mut Context, line: &str) -> Option<Value> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(err) => {
+            let err = RpcError::new(PARSE_ERROR, err.to_string());
+            return Some(error_response(&Value::Null, &err));
+        }
+    };
+    match value {
+        Value::Array(batch) if !batch.is_empty() => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|message| respond(context, message))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        Value::Array(_) => {
+            let err = RpcError::new(INVALID_REQUEST, MSG_INVALID_REQUEST);
+            Some(error_response(&Value::Null, &err))
+        }
+        message => respond(context, message),
+    }
+}
+
+/// Serves requests from stdin until EOF.
+///
+/// # Errors
+///
+/// Returns an error if stdin cannot be read or stdout cannot be written.
+pub fn serve(context: &mut Context) -> Result<(), Error> {
+    let mut writer = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond_line(context, &line) {
+            writeln!(writer, "{response}")?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
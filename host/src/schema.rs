@@ -0,0 +1,237 @@
+//! JSON Schema and TypeScript definitions of the messages, derived from [`crate::message`].
+//!
+//! The extension's definitions are generated from the JSON Schema, which only uses the keywords
+//! that `schemars` emits for the message types.
+
+use std::{fmt::Write as _, sync::LazyLock};
+
+use schemars::{SchemaGenerator, generate::SchemaSettings};
+use serde_json::{Map, Value, json};
+
+use crate::message::{Request, Response};
+
+const DEFS_PREFIX: &str = "#/$defs/";
+
+const TYPESCRIPT_HEADER: &str = "\
+// Generated from host/src/message.rs by `noematic schema --typescript`. Do not edit.
+";
+
+const INDENT: usize = 2;
+
+/// Returns a JSON Schema of requests and responses, whose definitions include every message type.
+#[must_use]
+pub fn json_schema() -> Value {
+    let mut generator = SchemaGenerator::new(SchemaSettings::draft2020_12());
+    let request = generator.subschema_for::<Request>();
+    let response = generator.subschema_for::<Response>();
+    let definitions: Map<String, Value> = generator.take_definitions(true);
+    sort_keys(json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Noematic messages",
+        "anyOf": [request, response],
+        "$defs": definitions,
+    }))
+}
+
+/// The `action` of each variant of [`crate::message::RequestAction`], which are found once.
+static REQUEST_ACTIONS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    let schema = json_schema();
+    schema["$defs"]["Request"]["oneOf"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|variant| variant["properties"]["action"]["const"].as_str())
+        .map(String::from)
+        .collect()
+});
+
+/// Returns the `action` of each variant of [`crate::message::RequestAction`], as described by
+/// [`json_schema`].
+#[must_use]
+pub fn request_actions() -> &'static [String] {
+    &REQUEST_ACTIONS
+}
+
+/// Sorts the keys of every object, which are otherwise in insertion order if `serde_json`'s
+/// `preserve_order` feature is enabled by another crate in the build.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let map = entries
+                .into_iter()
+                .map(|(key, value)| (key, sort_keys(value)))
+                .collect();
+            Value::Object(map)
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+/// Returns TypeScript definitions of every type in [`json_schema`].
+#[must_use]
+pub fn typescript() -> String {
+    let schema = json_schema();
+    let mut ret = String::from(TYPESCRIPT_HEADER);
+    if let Some(definitions) = schema["$defs"].as_object() {
+        for (name, definition) in definitions {
+            ret.push('\n');
+            ret.push_str(&doc_comment(definition, 0));
+            let rendered = render(definition, 0);
+            // Multiline unions start on a new line.
+            let separator = if rendered.starts_with('\n') { "" } else { " " };
+            let _ = writeln!(ret, "export type {name} ={separator}{rendered};");
+        }
+    }
+    ret
+}
+
+fn doc_comment(schema: &Value, indent: usize) -> String {
+    let Some(description) = schema.get("description").and_then(Value::as_str) else {
+        return String::new();
+    };
+    let pad = " ".repeat(indent);
+    if !description.contains('\n') {
+        return format!("{pad}/** {description} */\n");
+    }
+    let mut ret = format!("{pad}/**\n");
+    for line in description.lines() {
+        let _ = if line.is_empty() {
+            writeln!(ret, "{pad} *")
+        } else {
+            writeln!(ret, "{pad} * {line}")
+        };
+    }
+    let _ = writeln!(ret, "{pad} */");
+    ret
+}
+
+fn literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+        value => value.to_string(),
+    }
+}
+
+fn render(schema: &Value, indent: usize) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference.trim_start_matches(DEFS_PREFIX).to_string();
+    }
+    if let Some(value) = schema.get("const") {
+        return literal(value);
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        let values: Vec<String> = values.iter().map(literal).collect();
+        return values.join(" | ");
+    }
+    let variants = schema
+        .get("oneOf")
+        .or_else(|| schema.get("anyOf"))
+        .and_then(Value::as_array);
+    match variants {
+        // The fields common to every variant, as in a struct with a flattened enum.
+        Some(variants) if schema.get("properties").is_some() => format!(
+            "{} & {}",
+            render_object(schema, indent),
+            render_union(variants, indent, true)
+        ),
+        Some(variants) => render_union(variants, indent, false),
+        None => render_type(schema, indent),
+    }
+}
+
+fn render_type(schema: &Value, indent: usize) -> String {
+    let type_name = |name: &str| match name {
+        "object" if schema.get("properties").is_some() => render_object(schema, indent),
+        "object" => match schema.get("additionalProperties") {
+            Some(value @ Value::Object(_)) => format!("Record<string, {}>", render(value, indent)),
+            _ => "Record<string, never>".to_string(),
+        },
+        "array" => {
+            let items = schema
+                .get("items")
+                .map_or_else(|| "unknown".to_string(), |items| render(items, indent));
+            if items.contains(' ') {
+                format!("({items})[]")
+            } else {
+                format!("{items}[]")
+            }
+        }
+        "integer" | "number" => "number".to_string(),
+        "string" | "boolean" | "null" => name.to_string(),
+        _ => "unknown".to_string(),
+    };
+    match schema.get("type") {
+        Some(Value::String(name)) => type_name(name),
+        Some(Value::Array(names)) => {
+            let names: Vec<String> = names
+                .iter()
+                .filter_map(Value::as_str)
+                .map(type_name)
+                .collect();
+            names.join(" | ")
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+fn render_object(schema: &Value, indent: usize) -> String {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return "Record<string, never>".to_string();
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let pad = " ".repeat(indent + INDENT);
+    let mut ret = String::from("{\n");
+    for (name, property) in properties {
+        ret.push_str(&doc_comment(property, indent + INDENT));
+        let optional = if required.contains(&name.as_str()) {
+            ""
+        } else {
+            "?"
+        };
+        let rendered = render(property, indent + INDENT);
+        let _ = writeln!(ret, "{pad}{name}{optional}: {rendered};");
+    }
+    ret.push_str(&" ".repeat(indent));
+    ret.push('}');
+    ret
+}
+
+fn render_union(variants: &[Value], indent: usize, parenthesize: bool) -> String {
+    let rendered: Vec<String> = variants
+        .iter()
+        .map(|variant| render(variant, indent + 2 * INDENT))
+        .collect();
+    let multiline = variants
+        .iter()
+        .zip(&rendered)
+        .any(|(variant, rendered)| rendered.contains('\n') || variant.get("description").is_some());
+    if !multiline {
+        let ret = rendered.join(" | ");
+        return if parenthesize {
+            format!("({ret})")
+        } else {
+            ret
+        };
+    }
+    let (open, close) = if parenthesize { ("(", ")") } else { ("", "") };
+    let pad = " ".repeat(indent + INDENT);
+    let mut ret = String::from(open);
+    for (variant, rendered) in variants.iter().zip(rendered) {
+        ret.push('\n');
+        ret.push_str(&doc_comment(variant, indent + INDENT));
+        let _ = write!(ret, "{pad}| {rendered}");
+    }
+    if parenthesize {
+        ret.push('\n');
+        ret.push_str(&" ".repeat(indent));
+    }
+    ret.push_str(close);
+    ret
+}
@@ -0,0 +1,132 @@
+//! Checks of the values of request fields, beyond those made by their types.
+
+use std::path::Path;
+
+use crate::{
+    config::Settings,
+    message::{FieldError, Query, Quote, RequestAction, Title, Url},
+};
+
+/// The longest title, in characters, that is saved.
+pub const MAX_TITLE_LENGTH: usize = 1024;
+
+/// The schemes of the URLs of sites.
+const URL_SCHEMES: [&str; 3] = ["http", "https", "file"];
+
+const FIELD_URL: &str = "url";
+const FIELD_TITLE: &str = "title";
+const FIELD_QUERY: &str = "query";
+const FIELD_PAGE_LENGTH: &str = "pageLength";
+const FIELD_QUOTE: &str = "quote";
+const FIELD_PATH: &str = "path";
+const MSG_INVALID_URL: &str = "Not an absolute URL";
+const MSG_INVALID_SCHEME: &str = "Scheme must be http, https or file";
+const MSG_TITLE_TOO_LONG: &str = "Title too long";
+const MSG_EMPTY_QUERY: &str = "Query must not be empty";
+const MSG_ZERO_PAGE_LENGTH: &str = "pageLength must be at least 1";
+const MSG_PAGE_LENGTH_TOO_LARGE: &str = "pageLength too large";
+const MSG_QUOTE_RANGE: &str = "Quote start must not be after its end";
+const MSG_RELATIVE_PATH: &str = "Path must be absolute";
+const MSG_PATH_EXISTS: &str = "A file already exists at the path";
+
+fn field_error(field: &str, message: impl Into<String>) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Only the URLs of sites being saved are checked, so that sites saved with other URLs can still
+/// be found and removed.
+fn check_url(url: &Url, errors: &mut Vec<FieldError>) {
+    match url::Url::parse(url.as_str()) {
+        Ok(parsed) if URL_SCHEMES.contains(&parsed.scheme()) => {}
+        Ok(_) => errors.push(field_error(FIELD_URL, MSG_INVALID_SCHEME)),
+        Err(err) => errors.push(field_error(FIELD_URL, format!("{MSG_INVALID_URL}: {err}"))),
+    }
+}
+
+fn check_title(title: &Title, errors: &mut Vec<FieldError>) {
+    let length = title.as_str().chars().count();
+    if length > MAX_TITLE_LENGTH {
+        let message = format!("{MSG_TITLE_TOO_LONG}: {length} > {MAX_TITLE_LENGTH} characters");
+        errors.push(field_error(FIELD_TITLE, message));
+    }
+}
+
+fn check_query(query: &Query, errors: &mut Vec<FieldError>) {
+    if query.as_str().trim().is_empty() {
+        errors.push(field_error(FIELD_QUERY, MSG_EMPTY_QUERY));
+    }
+}
+
+fn check_quote(quote: Option<&Quote>, errors: &mut Vec<FieldError>) {
+    if quote.is_some_and(|quote| quote.start > quote.end) {
+        errors.push(field_error(FIELD_QUOTE, MSG_QUOTE_RANGE));
+    }
+}
+
+/// A backup is only written to a new file, at a path that does not depend on the directory in
+/// which the browser started the host.
+fn check_backup_path(path: &Path, errors: &mut Vec<FieldError>) {
+    if !path.is_absolute() {
+        errors.push(field_error(FIELD_PATH, MSG_RELATIVE_PATH));
+    } else if path.exists() {
+        errors.push(field_error(FIELD_PATH, MSG_PATH_EXISTS));
+    }
+}
+
+fn check_page_length(page_length: usize, max_page_length: usize, errors: &mut Vec<FieldError>) {
+    if page_length == 0 {
+        errors.push(field_error(FIELD_PAGE_LENGTH, MSG_ZERO_PAGE_LENGTH));
+    } else if page_length > max_page_length {
+        let message = format!("{MSG_PAGE_LENGTH_TOO_LARGE}: {page_length} > {max_page_length}");
+        errors.push(field_error(FIELD_PAGE_LENGTH, message));
+    }
+}
+
+/// Returns the fields of `action`'s payload whose values are not accepted with `settings`.
+pub fn validate(action: &RequestAction, settings: &Settings) -> Vec<FieldError> {
+    let mut ret = Vec::new();
+    match action {
+        RequestAction::SaveRequest { payload } => {
+            check_url(&payload.url, &mut ret);
+            check_title(&payload.title, &mut ret);
+        }
+        RequestAction::SaveBeginRequest { payload } => {
+            check_url(&payload.url, &mut ret);
+            check_title(&payload.title, &mut ret);
+        }
+        RequestAction::AddAnnotationRequest { payload } => {
+            check_quote(payload.quote.as_ref(), &mut ret);
+        }
+        RequestAction::EditAnnotationRequest { payload } => {
+            check_quote(payload.quote.as_ref(), &mut ret);
+        }
+        RequestAction::SearchRequest { payload } => {
+            check_query(&payload.query, &mut ret);
+            check_page_length(payload.page_length, settings.max_page_length, &mut ret);
+        }
+        RequestAction::BackupRequest { payload } => check_backup_path(&payload.path, &mut ret),
+        RequestAction::ListRequest { payload } => {
+            check_page_length(payload.page_length, settings.max_page_length, &mut ret);
+        }
+        RequestAction::RemoveRequest { .. }
+        | RequestAction::RestoreRequest { .. }
+        | RequestAction::GetRequest { .. }
+        | RequestAction::SetStatusRequest { .. }
+        | RequestAction::ListAnnotationsRequest { .. }
+        | RequestAction::RemoveAnnotationRequest { .. }
+        | RequestAction::EmptyTrashRequest { .. }
+        | RequestAction::StatsRequest { .. }
+        | RequestAction::MaintenanceRequest { .. }
+        | RequestAction::ConfigRequest { .. }
+        | RequestAction::SubscribeRequest { .. }
+        | RequestAction::HelloRequest { .. }
+        | RequestAction::CapabilitiesRequest { .. }
+        | RequestAction::SaveChunkRequest { .. }
+        | RequestAction::SaveCommitRequest { .. }
+        | RequestAction::CancelRequest { .. } => {}
+    }
+    ret
+}
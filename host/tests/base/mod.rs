@@ -1,9 +1,11 @@
 use std::{
     io::{Read, Write},
+    net::TcpStream,
     path::PathBuf,
+    process::Child,
 };
 
-use anyhow::Error;
+use anyhow::{Error, anyhow};
 use serde_json::Value;
 
 /// Returns the path to the `noematic` binary under test.
@@ -33,3 +35,56 @@ pub fn read_response(reader: &mut impl Read) -> Result<Value, Error> {
     reader.read_exact(&mut response_bytes)?;
     serde_json::from_slice::<Value>(&response_bytes).map_err(Into::into)
 }
+
+/// Writes a request to the child's stdin and reads `count` responses from its stdout.
+pub fn exchange(child: &mut Child, request: &Value, count: usize) -> Result<Vec<Value>, Error> {
+    let stdin = child
+        .stdin
+        .as_mut()
+        .ok_or_else(|| anyhow!("Failed to open stdin"))?;
+    write_request(stdin, request)?;
+    let stdout = child
+        .stdout
+        .as_mut()
+        .ok_or_else(|| anyhow!("Failed to open stdout"))?;
+    (0..count).map(|_| read_response(stdout)).collect()
+}
+
+/// Sends an HTTP request to `addr` with `headers`, returning the status and the body.
+///
+/// The `Host` header defaults to `addr`.
+pub fn http_request(
+    addr: &str,
+    method: &str,
+    target: &str,
+    headers: &[(&str, &str)],
+    body: Option<&Value>,
+) -> Result<(u16, String), Error> {
+    let mut stream = TcpStream::connect(addr)?;
+    let body = body.map(Value::to_string).unwrap_or_default();
+    write!(
+        stream,
+        "{method} {target} HTTP/1.1\r\nConnection: close\r\n"
+    )?;
+    if !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("Host"))
+    {
+        write!(stream, "Host: {addr}\r\n")?;
+    }
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(stream, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("Malformed response"))?;
+    let status = head
+        .split(' ')
+        .nth(1)
+        .ok_or_else(|| anyhow!("Malformed status line"))?
+        .parse()?;
+    Ok((status, body.to_string()))
+}
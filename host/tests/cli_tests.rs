@@ -1,8 +1,11 @@
 mod base;
 
-use std::process::{Command, Stdio};
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+};
 
-use serde_json::json;
+use serde_json::{Value, json};
 
 const COMMAND_ARG: &str = "--test";
 const VERSION: &str = "0.1.0";
@@ -119,6 +122,8 @@ fn test_search() {
             "url": "https://en.wikipedia.org/wiki/Foobar",
             "title": "Title",
             "snippet": "Foo bar baz <b>quux</b>",
+            "passage": { "start": 0, "end": 16 },
+            "status": "unread",
         },
         "correlationId": CORRELATION_ID
     });
@@ -205,6 +210,8 @@ fn test_search_quotation() {
             "url": "https://en.wikipedia.org/wiki/Foobar",
             "title": "Title",
             "snippet": "<b>foo</b> <b>bar</b> baz quux",
+            "passage": { "start": 0, "end": 16 },
+            "status": "unread",
         },
         "correlationId": CORRELATION_ID
     });
@@ -291,6 +298,8 @@ fn search_idempotent() {
             "url": "https://en.wikipedia.org/wiki/Foobar",
             "title": "Title",
             "snippet": "Foo bar baz <b>quux</b>",
+            "passage": { "start": 0, "end": 16 },
+            "status": "unread",
         },
         "correlationId": CORRELATION_ID
     });
@@ -400,3 +409,3201 @@ fn test_remove() {
     let status = child.wait().expect("Failed to wait for child process");
     assert!(status.success())
 }
+
+#[test]
+fn test_search_long_document() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    // Characters outside the Basic Multilingual Plane are two UTF-16 code units.
+    let inner_text = format!(
+        "{}quux {}",
+        "lorem \u{1f600} ".repeat(500),
+        "ipsum ".repeat(500)
+    );
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": inner_text
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    base::write_request(stdin, &save_request).expect("Failed to write request");
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let _ = base::read_response(stdout).expect("Failed to read response");
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    base::write_request(stdin, &search_request).expect("Failed to write request");
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let header = base::read_response(stdout).expect("Failed to read response");
+    assert_eq!(header["payload"]["pageLength"], 1);
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let site = base::read_response(stdout).expect("Failed to read response");
+    let snippet = site["payload"]["snippet"]
+        .as_str()
+        .expect("Missing snippet");
+    assert!(snippet.contains("<b>quux</b>"));
+    let start = site["payload"]["passage"]["start"]
+        .as_u64()
+        .expect("Missing passage start");
+    let end = site["payload"]["passage"]["end"]
+        .as_u64()
+        .expect("Missing passage end");
+    let units: Vec<u16> = inner_text.encode_utf16().collect();
+    let passage =
+        String::from_utf16(&units[start as usize..end as usize]).expect("Invalid passage offsets");
+    assert!(start > 0);
+    assert!(passage.contains("quux"));
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_search_title_only() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Quux",
+            "innerText": "Foo bar baz"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    base::write_request(stdin, &save_request).expect("Failed to write request");
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let _ = base::read_response(stdout).expect("Failed to read response");
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    base::write_request(stdin, &search_request).expect("Failed to write request");
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let _ = base::read_response(stdout).expect("Failed to read response");
+
+    let expected = json!({
+        "version": VERSION,
+        "action": "searchResponseSite",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Quux",
+            "snippet": "Foo bar baz",
+            "passage": null,
+            "status": "unread",
+        },
+        "correlationId": CORRELATION_ID
+    });
+
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let actual = base::read_response(stdout).expect("Failed to read response");
+
+    assert_eq!(expected, actual);
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_annotations() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+
+    let add_request = json!({
+        "version": VERSION,
+        "action": "addAnnotationRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "note": "Remember lifetimes",
+            "quote": { "text": "baz", "start": 8, "end": 11 }
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &add_request, 1).expect("Failed to add");
+    let annotation = &responses[0]["payload"]["annotation"];
+    assert_eq!(responses[0]["action"], "addAnnotationResponse");
+    assert_eq!(annotation["url"], "https://en.wikipedia.org/wiki/Foobar");
+    assert_eq!(annotation["note"], "Remember lifetimes");
+    assert_eq!(
+        annotation["quote"],
+        json!({ "text": "baz", "start": 8, "end": 11 })
+    );
+    let id = annotation["id"].clone();
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "lifetimes",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(
+        responses[1]["payload"]["url"],
+        "https://en.wikipedia.org/wiki/Foobar"
+    );
+
+    let edit_request = json!({
+        "version": VERSION,
+        "action": "editAnnotationRequest",
+        "payload": {
+            "id": id,
+            "note": "Remember borrowing",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &edit_request, 1).expect("Failed to edit");
+    let annotation = &responses[0]["payload"]["annotation"];
+    assert_eq!(annotation["note"], "Remember borrowing");
+    assert_eq!(annotation["quote"], json!(null));
+
+    let responses = base::exchange(&mut child, &search_request, 1).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 0);
+
+    let list_request = json!({
+        "version": VERSION,
+        "action": "listAnnotationsRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &list_request, 1).expect("Failed to list");
+    let annotations = responses[0]["payload"]["annotations"]
+        .as_array()
+        .expect("Missing annotations");
+    assert_eq!(annotations.len(), 1);
+    assert_eq!(annotations[0]["id"], id);
+
+    let remove_request = json!({
+        "version": VERSION,
+        "action": "removeAnnotationRequest",
+        "payload": {
+            "id": id,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &remove_request, 1).expect("Failed to remove");
+    assert_eq!(responses[0]["action"], "removeAnnotationResponse");
+
+    let responses = base::exchange(&mut child, &list_request, 1).expect("Failed to list");
+    assert_eq!(responses[0]["payload"]["annotations"], json!([]));
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_annotation_unknown_url() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let add_request = json!({
+        "version": VERSION,
+        "action": "addAnnotationRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "note": "Remember lifetimes",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "addAnnotationResponse",
+        "payload": { "annotation": null },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &add_request, 1).expect("Failed to add");
+    assert_eq!(expected, responses[0]);
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_annotation_trashed_site() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let request = |action: &str, payload: Value| {
+        json!({
+            "version": VERSION,
+            "action": action,
+            "payload": payload,
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let add_request = request(
+        "addAnnotationRequest",
+        json!({ "url": url, "note": "Remember lifetimes" }),
+    );
+    let list_request = request("listAnnotationsRequest", json!({ "url": url }));
+    let requests = [
+        request(
+            "saveRequest",
+            json!({ "url": url, "title": "Title", "innerText": "Foo bar baz quux" }),
+        ),
+        add_request.clone(),
+        request("removeRequest", json!({ "url": url })),
+        add_request,
+        list_request.clone(),
+        request("restoreRequest", json!({ "url": url })),
+        list_request,
+    ];
+    let responses = run_with_args([COMMAND_ARG], &requests, requests.len());
+
+    // A site in the trash cannot be annotated, and its annotations are hidden until it is
+    // restored.
+    assert_eq!(responses[3]["payload"], json!({ "annotation": null }));
+    assert_eq!(responses[4]["payload"], json!({ "annotations": [] }));
+    assert_eq!(
+        responses[6]["payload"]["annotations"],
+        json!([responses[1]["payload"]["annotation"]])
+    );
+}
+
+#[test]
+fn test_status() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    for (url, title) in [
+        ("https://en.wikipedia.org/wiki/Foobar", "Foobar"),
+        ("https://en.wikipedia.org/wiki/Quux", "Quux"),
+    ] {
+        let save_request = json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": {
+                "url": url,
+                "title": title,
+                "innerText": "Foo bar baz quux"
+            },
+            "correlationId": CORRELATION_ID
+        });
+        base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+    }
+
+    let set_status_request = json!({
+        "version": VERSION,
+        "action": "setStatusRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Quux",
+            "status": "read",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "setStatusResponse",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses =
+        base::exchange(&mut child, &set_status_request, 1).expect("Failed to set status");
+    assert_eq!(expected, responses[0]);
+
+    let list_request = json!({
+        "version": VERSION,
+        "action": "listRequest",
+        "payload": {
+            "pageNum": 0,
+            "pageLength": 10,
+            "status": "unread",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &list_request, 2).expect("Failed to list");
+    assert_eq!(responses[0]["action"], "listResponseHeader");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[0]["payload"]["hasMore"], false);
+    assert_eq!(responses[1]["action"], "listResponseSite");
+    assert_eq!(
+        responses[1]["payload"]["url"],
+        "https://en.wikipedia.org/wiki/Foobar"
+    );
+    assert_eq!(responses[1]["payload"]["statusUpdatedAt"], json!(null));
+
+    let list_request = json!({
+        "version": VERSION,
+        "action": "listRequest",
+        "payload": {
+            "pageNum": 0,
+            "pageLength": 1,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &list_request, 2).expect("Failed to list");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[0]["payload"]["hasMore"], true);
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+            "status": "read",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(
+        responses[1]["payload"]["url"],
+        "https://en.wikipedia.org/wiki/Quux"
+    );
+    assert_eq!(responses[1]["payload"]["status"], "read");
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_trash() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": url,
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+
+    let remove_request = json!({
+        "version": VERSION,
+        "action": "removeRequest",
+        "payload": { "url": url },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &remove_request, 1).expect("Failed to remove");
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &search_request, 1).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 0);
+
+    let list_trash_request = json!({
+        "version": VERSION,
+        "action": "listRequest",
+        "payload": {
+            "pageNum": 0,
+            "pageLength": 10,
+            "trashed": true,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &list_trash_request, 2).expect("Failed to list");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[1]["payload"]["url"], url);
+
+    let restore_request = json!({
+        "version": VERSION,
+        "action": "restoreRequest",
+        "payload": { "url": url },
+        "correlationId": CORRELATION_ID
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "restoreResponse",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &restore_request, 1).expect("Failed to restore");
+    assert_eq!(expected, responses[0]);
+
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[1]["payload"]["url"], url);
+
+    base::exchange(&mut child, &remove_request, 1).expect("Failed to remove");
+
+    let empty_trash_request = json!({
+        "version": VERSION,
+        "action": "emptyTrashRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "emptyTrashResponse",
+        "payload": { "removed": 1 },
+        "correlationId": CORRELATION_ID
+    });
+    let responses =
+        base::exchange(&mut child, &empty_trash_request, 1).expect("Failed to empty trash");
+    assert_eq!(expected, responses[0]);
+
+    base::exchange(&mut child, &restore_request, 1).expect("Failed to restore");
+    let responses = base::exchange(&mut child, &search_request, 1).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 0);
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_stats() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    for url in [
+        "https://en.wikipedia.org/wiki/Foobar",
+        "https://user@en.wikipedia.org:443/wiki/Quux",
+        "http://[::1]:8080/notes",
+        "https://example.com/",
+    ] {
+        let save_request = json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": {
+                "url": url,
+                "title": "Title",
+                "innerText": "Foo bar baz quux"
+            },
+            "correlationId": CORRELATION_ID
+        });
+        base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+    }
+
+    let set_status_request = json!({
+        "version": VERSION,
+        "action": "setStatusRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "status": "archived",
+        },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &set_status_request, 1).expect("Failed to set status");
+
+    let remove_request = json!({
+        "version": VERSION,
+        "action": "removeRequest",
+        "payload": { "url": "https://example.com/" },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &remove_request, 1).expect("Failed to remove");
+
+    let stats_request = json!({
+        "version": VERSION,
+        "action": "statsRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &stats_request, 1).expect("Failed to get stats");
+    let stats = &responses[0]["payload"];
+    assert_eq!(responses[0]["action"], "statsResponse");
+    assert_eq!(stats["totalSites"], 3);
+    assert_eq!(
+        stats["statusCounts"],
+        json!({ "unread": 2, "read": 0, "archived": 1 })
+    );
+    assert_eq!(stats["trashedSites"], 1);
+    assert_eq!(stats["totalTextBytes"], 48);
+    assert_eq!(
+        stats["topDomains"],
+        json!([
+            { "domain": "en.wikipedia.org", "count": 2 },
+            { "domain": "[::1]", "count": 1 }
+        ])
+    );
+    assert!(
+        stats["databaseSize"]
+            .as_u64()
+            .expect("Missing databaseSize")
+            > 0
+    );
+    assert!(
+        stats["ftsIndexSize"]
+            .as_u64()
+            .expect("Missing ftsIndexSize")
+            > 0
+    );
+    assert!(stats["oldestCreatedAt"].is_string());
+    assert!(stats["newestCreatedAt"].is_string());
+    assert!(stats["schemaVersion"].is_string());
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_stats_command() {
+    let noematic = base::exe();
+    let output = Command::new(noematic)
+        .args([COMMAND_ARG, "stats"])
+        .output()
+        .expect("Failed to run child process");
+    assert!(output.status.success());
+
+    let stats: Value = serde_json::from_slice(&output.stdout).expect("Failed to parse stats");
+    assert_eq!(stats["totalSites"], 0);
+    assert_eq!(stats["topDomains"], json!([]));
+    assert_eq!(stats["oldestCreatedAt"], json!(null));
+}
+
+#[test]
+fn test_backup_request() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let backup_path = dir.path().join("backup.sqlite3");
+
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+
+    let backup_request = json!({
+        "version": VERSION,
+        "action": "backupRequest",
+        "payload": { "path": backup_path },
+        "correlationId": CORRELATION_ID
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "backupResponse",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &backup_request, 1).expect("Failed to back up");
+    assert_eq!(expected, responses[0]);
+
+    // An existing file is not replaced, and a relative path is refused.
+    for path in [
+        backup_path.clone(),
+        std::path::PathBuf::from("backup.sqlite3"),
+    ] {
+        let backup_request = json!({
+            "version": VERSION,
+            "action": "backupRequest",
+            "payload": { "path": path },
+            "correlationId": CORRELATION_ID
+        });
+        let responses = base::exchange(&mut child, &backup_request, 1).expect("Failed to back up");
+        assert_eq!(responses[0]["action"], "errorResponse");
+        assert_eq!(responses[0]["payload"]["fields"][0]["field"], "path");
+    }
+    let output = Command::new(base::exe())
+        .arg(COMMAND_ARG)
+        .arg("backup")
+        .arg(&backup_path)
+        .output()
+        .expect("Failed to run backup");
+    assert!(!output.status.success());
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    let connection = rusqlite::Connection::open(&backup_path).expect("Failed to open backup");
+    let url: String = connection
+        .query_row("SELECT url FROM sites", [], |row| row.get(0))
+        .expect("Failed to query backup");
+    assert_eq!(url, "https://en.wikipedia.org/wiki/Foobar");
+}
+
+/// Runs the host with `args`, sending `requests` and returning the responses, of which there are
+/// `count`.
+fn run_with_args<I, S>(args: I, requests: &[Value], count: usize) -> Vec<Value>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    for request in requests {
+        base::write_request(stdin, request).expect("Failed to write request");
+    }
+    drop(child.stdin.take());
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let responses = (0..count)
+        .map(|_| base::read_response(stdout).expect("Failed to read response"))
+        .collect();
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+    responses
+}
+
+/// Runs the host against the database at `db_path`, without a daemon, sending `requests` and
+/// returning the responses, of which there are `count`.
+fn run_persistent(db_path: &std::path::Path, requests: &[Value], count: usize) -> Vec<Value> {
+    run_with_args(
+        [
+            std::ffi::OsStr::new("--no-daemon"),
+            std::ffi::OsStr::new("--db"),
+            db_path.as_os_str(),
+        ],
+        requests,
+        count,
+    )
+}
+
+#[test]
+fn test_backup_restore() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let backup_path = dir.path().join("backup.sqlite3");
+    let save = |url: &str| {
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": {
+                "url": url,
+                "title": "Title",
+                "innerText": "Foo bar baz quux"
+            },
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+
+    run_persistent(&db_path, &[save("https://example.com/a")], 1);
+
+    let status = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("backup")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run backup");
+    assert!(status.success());
+
+    run_persistent(&db_path, &[save("https://example.com/b")], 1);
+
+    let garbage_path = dir.path().join("garbage.sqlite3");
+    std::fs::write(&garbage_path, b"garbage").expect("Failed to write garbage");
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("restore")
+        .arg(&garbage_path)
+        .output()
+        .expect("Failed to run restore");
+    assert!(!output.status.success());
+
+    let responses = run_persistent(&db_path, std::slice::from_ref(&search_request), 3);
+    assert_eq!(responses[0]["payload"]["pageLength"], 2);
+
+    // Another process keeps using the database while it is restored.
+    let mut child = Command::new(base::exe())
+        .args(["--no-daemon", "--db"])
+        .arg(&db_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    base::exchange(&mut child, &save("https://example.com/c"), 1).expect("Failed to save");
+
+    let status = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("restore")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run restore");
+    assert!(status.success());
+
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[1]["payload"]["url"], "https://example.com/a");
+    base::exchange(&mut child, &save("https://example.com/d"), 1).expect("Failed to save");
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    // The database was backed up before it was restored.
+    let backups: Vec<std::path::PathBuf> = std::fs::read_dir(dir.path().join("backups"))
+        .expect("Failed to read backups")
+        .map(|entry| entry.expect("Failed to read entry").path())
+        .collect();
+    assert_eq!(backups.len(), 1);
+    let name = backups[0].file_name().and_then(|name| name.to_str());
+    assert!(name.is_some_and(|name| name.starts_with("pre-restore-")));
+    let responses = run_persistent(&backups[0], std::slice::from_ref(&search_request), 4);
+    assert_eq!(responses[0]["payload"]["pageLength"], 3);
+
+    let responses = run_persistent(&db_path, &[search_request], 3);
+    assert_eq!(responses[0]["payload"]["pageLength"], 2);
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("doctor")
+        .output()
+        .expect("Failed to run doctor");
+    assert!(output.status.success());
+}
+
+#[test]
+fn test_migration_backup() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    {
+        let connection = rusqlite::Connection::open(&db_path).expect("Failed to open database");
+        connection
+            .execute_batch(include_str!("fixtures/create-0.1.0.sql"))
+            .expect("Failed to create database");
+        connection
+            .execute(
+                "INSERT INTO sites (url, title, inner_text) VALUES (?, ?, ?)",
+                ["https://example.com/", "Title", "Foo bar baz quux"],
+            )
+            .expect("Failed to insert site");
+    }
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = run_persistent(&db_path, &[search_request], 2);
+    assert_eq!(responses[1]["payload"]["url"], "https://example.com/");
+    assert_eq!(
+        responses[1]["payload"]["passage"],
+        json!({ "start": 0, "end": 16 })
+    );
+
+    let backups: Vec<_> = std::fs::read_dir(dir.path().join("backups"))
+        .expect("Failed to read backups directory")
+        .collect();
+    assert_eq!(backups.len(), 1);
+    let backup = backups[0].as_ref().expect("Failed to read backup").path();
+    let connection = rusqlite::Connection::open(backup).expect("Failed to open backup");
+    let minor: u64 = connection
+        .query_row("SELECT minor FROM schema_version", [], |row| row.get(0))
+        .expect("Failed to query backup");
+    assert_eq!(minor, 1);
+}
+
+#[test]
+fn test_maintenance_request() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg(COMMAND_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+
+    let maintenance_request = json!({
+        "version": VERSION,
+        "action": "maintenanceRequest",
+        "payload": { "rebuild": true, "optimize": true },
+        "correlationId": CORRELATION_ID
+    });
+    let responses =
+        base::exchange(&mut child, &maintenance_request, 1).expect("Failed to run maintenance");
+    let findings = json!({
+        "integrityCheck": [],
+        "ftsIntegrityCheck": [],
+        "sites": 1,
+        "indexedSites": 1,
+        "unindexedSites": 0,
+        "sitesWithoutPassages": 0,
+        "healthy": true,
+    });
+    let expected = json!({
+        "version": VERSION,
+        "action": "maintenanceResponse",
+        "payload": {
+            "before": findings,
+            "after": findings,
+            "rebuilt": true,
+            "optimized": true,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(expected, responses[0]);
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(
+        responses[1]["payload"]["snippet"],
+        "Foo bar baz <b>quux</b>"
+    );
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[test]
+fn test_doctor_rebuild() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    run_persistent(&db_path, &[save_request], 1);
+
+    {
+        let connection = rusqlite::Connection::open(&db_path).expect("Failed to open database");
+        connection
+            .execute("DELETE FROM sites_fts", [])
+            .expect("Failed to delete from sites_fts");
+    }
+
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("doctor")
+        .output()
+        .expect("Failed to run doctor");
+    assert!(!output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).expect("Failed to parse report");
+    assert_eq!(report["before"]["healthy"], false);
+    assert_eq!(report["before"]["sites"], 1);
+    assert_eq!(report["before"]["indexedSites"], 0);
+    assert_eq!(report["before"]["unindexedSites"], 1);
+    assert_eq!(report["after"], json!(null));
+
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .args(["doctor", "--rebuild"])
+        .output()
+        .expect("Failed to run doctor");
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).expect("Failed to parse report");
+    assert_eq!(report["rebuilt"], true);
+    assert_eq!(report["after"]["healthy"], true);
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = run_persistent(&db_path, &[search_request], 2);
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+}
+
+#[test]
+fn test_db_environment() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("env.sqlite3");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let mut child = Command::new(base::exe())
+        .env("NOEMATIC_DB", &db_path)
+        .env("NOEMATIC_NO_DAEMON", "true")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = run_persistent(&db_path, &[search_request], 2);
+    assert_eq!(
+        responses[1]["payload"]["url"],
+        "https://en.wikipedia.org/wiki/Foobar"
+    );
+}
+
+#[test]
+fn test_profiles() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let config_path = dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        "[profiles.work]\ndb = \"work.sqlite3\"\n\n[profiles.personal]\ndb = \"personal.sqlite3\"\n",
+    )
+    .expect("Failed to write config");
+    let config_path = config_path.to_str().expect("Invalid config path");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+
+    let work = ["--no-daemon", "--config", config_path, "--profile", "work"];
+    let personal = [
+        "--no-daemon",
+        "--config",
+        config_path,
+        "--profile",
+        "personal",
+    ];
+    run_with_args(work, &[save_request], 1);
+    assert!(dir.path().join("work.sqlite3").exists());
+
+    let responses = run_with_args(work, std::slice::from_ref(&search_request), 2);
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    let responses = run_with_args(personal, &[search_request], 1);
+    assert_eq!(responses[0]["payload"]["pageLength"], 0);
+
+    let output = Command::new(base::exe())
+        .args(["--config", config_path, "--profile", "missing", "stats"])
+        .output()
+        .expect("Failed to run stats");
+    assert!(!output.status.success());
+}
+
+/// Runs the host with `args` and `envs`, returning the effective config.
+fn get_config(args: &[&str], envs: &[(&str, &str)]) -> Value {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let config_request = json!({
+        "version": VERSION,
+        "action": "configRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let mut responses =
+        base::exchange(&mut child, &config_request, 1).expect("Failed to get config");
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+    assert_eq!(responses[0]["action"], "configResponse");
+    responses[0]["payload"].take()
+}
+
+#[test]
+fn test_config_precedence() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let missing_path = dir.path().join("missing.toml");
+    let missing_path = missing_path.to_str().expect("Invalid config path");
+    let config = get_config(&[COMMAND_ARG, "--config", missing_path], &[]);
+    let expected = json!({
+        "configFile": null,
+        "profile": null,
+        "db": null,
+        "keyFile": null,
+        "trashRetentionDays": 30,
+        "queryRegex": "\\W+",
+        "snippetTokens": 40,
+        "maxPageLength": 100,
+        "maxRequestSize": 67108864,
+    });
+    assert_eq!(expected, config);
+
+    let config_path = dir.path().join("config.toml");
+    std::fs::write(
+        &config_path,
+        "trash_retention_days = 7\n\n[search]\nsnippet_tokens = 10\nmax_page_length = 5\n",
+    )
+    .expect("Failed to write config");
+    let config_path = config_path.to_str().expect("Invalid config path");
+    let config = get_config(&[COMMAND_ARG, "--config", config_path], &[]);
+    assert_eq!(config["configFile"], config_path);
+    assert_eq!(config["trashRetentionDays"], 7);
+    assert_eq!(config["snippetTokens"], 10);
+    assert_eq!(config["maxPageLength"], 5);
+
+    let envs = [
+        ("NOEMATIC_CONFIG", config_path),
+        ("NOEMATIC_SNIPPET_TOKENS", "20"),
+    ];
+    let config = get_config(&[COMMAND_ARG], &envs);
+    assert_eq!(expected, config);
+    // Even an invalid value in the environment is ignored with --test.
+    let config = get_config(
+        &[COMMAND_ARG],
+        &[&envs[..], &[("NOEMATIC_MAX_PAGE_LENGTH", "many")]].concat(),
+    );
+    assert_eq!(expected, config);
+
+    let db_path = dir.path().join("db.sqlite3");
+    let db_path = db_path.to_str().expect("Invalid database path");
+    let args = ["--no-daemon", "--db", db_path];
+    let config = get_config(&args, &envs);
+    assert_eq!(config["configFile"], config_path);
+    assert_eq!(config["db"], db_path);
+    assert_eq!(config["snippetTokens"], 20);
+    assert_eq!(config["maxPageLength"], 5);
+
+    let config = get_config(&[&args[..], &["--snippet-tokens", "30"]].concat(), &envs);
+    assert_eq!(config["snippetTokens"], 30);
+    assert_eq!(config["trashRetentionDays"], 7);
+}
+
+#[test]
+fn test_config_invalid() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let config_path = dir.path().join("config.toml");
+    for contents in [
+        "[search]\nsnippet_tokens = 100\n",
+        "[search]\nmax_page_length = 0\n",
+        "[search]\nquery_regex = '('\n",
+        "[search]\nquery_regex = '\\W*'\n",
+        "[search]\nsnippet_length = 10\n",
+    ] {
+        std::fs::write(&config_path, contents).expect("Failed to write config");
+        let output = Command::new(base::exe())
+            .arg(COMMAND_ARG)
+            .arg("--config")
+            .arg(&config_path)
+            .arg("stats")
+            .output()
+            .expect("Failed to run stats");
+        assert!(!output.status.success(), "accepted {contents:?}");
+    }
+
+    let output = Command::new(base::exe())
+        .args([COMMAND_ARG, "--snippet-tokens", "0", "stats"])
+        .output()
+        .expect("Failed to run stats");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_config_max_page_length() {
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .args([COMMAND_ARG, "--max-page-length", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    for url in ["https://example.com/a", "https://example.com/b"] {
+        let save_request = json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": {
+                "url": url,
+                "title": "Title",
+                "innerText": "Foo bar baz quux"
+            },
+            "correlationId": CORRELATION_ID
+        });
+        base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+    }
+    let search_request = |page_length: usize| {
+        json!({
+            "version": VERSION,
+            "action": "searchRequest",
+            "payload": {
+                "query": "quux",
+                "pageNum": 0,
+                "pageLength": page_length,
+            },
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let responses = base::exchange(&mut child, &search_request(10), 1).expect("Failed to search");
+    assert_eq!(responses[0]["action"], "errorResponse");
+    assert_eq!(responses[0]["payload"]["kind"], "invalidRequest");
+    assert_eq!(responses[0]["payload"]["fields"][0]["field"], "pageLength");
+    let responses = base::exchange(&mut child, &search_request(1), 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    assert_eq!(responses[0]["payload"]["hasMore"], true);
+
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encryption() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let key_path = dir.path().join("key");
+    std::fs::write(&key_path, "correct horse battery staple\n").expect("Failed to write key");
+    let wrong_key_path = dir.path().join("wrong-key");
+    std::fs::write(&wrong_key_path, "hunter2\n").expect("Failed to write key");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let run = |key_path: &std::path::Path, command: &str| {
+        Command::new(base::exe())
+            .arg("--db")
+            .arg(&db_path)
+            .arg("--key-file")
+            .arg(key_path)
+            .arg(command)
+            .output()
+            .expect("Failed to run command")
+    };
+
+    run_persistent(&db_path, &[save_request], 1);
+    assert!(run(&key_path, "encrypt").status.success());
+
+    {
+        let connection = rusqlite::Connection::open(&db_path).expect("Failed to open database");
+        let result =
+            connection.query_row("SELECT COUNT(*) FROM sites", [], |row| row.get::<_, i64>(0));
+        assert!(result.is_err());
+    }
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("stats")
+        .output()
+        .expect("Failed to run stats");
+    assert!(!output.status.success());
+    assert!(!run(&wrong_key_path, "stats").status.success());
+
+    let args = [
+        std::ffi::OsStr::new("--no-daemon"),
+        std::ffi::OsStr::new("--db"),
+        db_path.as_os_str(),
+        std::ffi::OsStr::new("--key-file"),
+        key_path.as_os_str(),
+    ];
+    let responses = run_with_args(args, std::slice::from_ref(&search_request), 2);
+    assert_eq!(
+        responses[1]["payload"]["url"],
+        "https://en.wikipedia.org/wiki/Foobar"
+    );
+
+    let backup_path = dir.path().join("backup.sqlite3");
+    let status = Command::new(base::exe())
+        .args(args)
+        .arg("backup")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run backup");
+    assert!(status.success());
+    let status = Command::new(base::exe())
+        .args(args)
+        .arg("restore")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run restore");
+    assert!(status.success());
+
+    // The database cannot be decrypted while another process has it open.
+    let mut child = Command::new(base::exe())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert!(!run(&key_path, "decrypt").status.success());
+    let responses = base::exchange(&mut child, &search_request, 2).expect("Failed to search");
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    assert!(run(&key_path, "decrypt").status.success());
+    let responses = run_persistent(&db_path, &[search_request], 2);
+    assert_eq!(responses[0]["payload"]["pageLength"], 1);
+}
+
+#[cfg(not(feature = "encryption"))]
+#[test]
+fn test_encryption_unsupported() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let key_path = dir.path().join("key");
+    std::fs::write(&key_path, "correct horse battery staple\n").expect("Failed to write key");
+    for command in ["stats", "encrypt"] {
+        let output = Command::new(base::exe())
+            .arg("--db")
+            .arg(&db_path)
+            .arg("--key-file")
+            .arg(&key_path)
+            .arg(command)
+            .output()
+            .expect("Failed to run command");
+        assert!(!output.status.success());
+    }
+}
+
+#[test]
+fn test_concurrent_access() {
+    const PROCESSES: usize = 4;
+    const SITES: usize = 10;
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    std::thread::scope(|scope| {
+        for process in 0..PROCESSES {
+            let db_path = &db_path;
+            scope.spawn(move || {
+                let mut requests = Vec::new();
+                for site in 0..SITES {
+                    requests.push(json!({
+                        "version": VERSION,
+                        "action": "saveRequest",
+                        "payload": {
+                            "url": format!("https://example.com/{process}/{site}"),
+                            "title": "Title",
+                            "innerText": "Foo bar baz quux"
+                        },
+                        "correlationId": CORRELATION_ID
+                    }));
+                    requests.push(json!({
+                        "version": VERSION,
+                        "action": "searchRequest",
+                        "payload": {
+                            "query": "quux",
+                            "pageNum": 0,
+                            "pageLength": 1,
+                        },
+                        "correlationId": CORRELATION_ID
+                    }));
+                }
+                // Each save has one response, and each search a header and one site.
+                let responses = run_persistent(db_path, &requests, SITES * 3);
+                for response in responses {
+                    assert_ne!(response["action"], json!(null));
+                }
+            });
+        }
+    });
+
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("stats")
+        .output()
+        .expect("Failed to run stats");
+    assert!(output.status.success());
+    let stats: Value = serde_json::from_slice(&output.stdout).expect("Failed to parse stats");
+    assert_eq!(stats["totalSites"], PROCESSES * SITES);
+}
+
+#[test]
+fn test_subscribe() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let requests = [
+        json!({
+            "version": VERSION,
+            "action": "subscribeRequest",
+            "payload": {},
+            "correlationId": CORRELATION_ID
+        }),
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": { "url": url, "title": "Title", "innerText": "Inner text" },
+            "correlationId": CORRELATION_ID
+        }),
+        json!({
+            "version": VERSION,
+            "action": "removeRequest",
+            "payload": { "url": url },
+            "correlationId": CORRELATION_ID
+        }),
+    ];
+    let responses = run_with_args([COMMAND_ARG], &requests, 5);
+    let actions: Vec<&Value> = responses
+        .iter()
+        .map(|response| &response["action"])
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "subscribeResponse",
+            "saveResponse",
+            "siteChanged",
+            "removeResponse",
+            "siteChanged"
+        ]
+    );
+    assert_eq!(
+        responses[2]["payload"],
+        json!({ "url": url, "change": "saved" })
+    );
+    assert_eq!(
+        responses[4]["payload"],
+        json!({ "url": url, "change": "removed" })
+    );
+}
+
+#[test]
+fn test_subscribe_other_process() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+
+    let mut subscriber = Command::new(base::exe())
+        .arg("--no-daemon")
+        .arg("--db")
+        .arg(&db_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let subscribe_request = json!({
+        "version": VERSION,
+        "action": "subscribeRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses =
+        base::exchange(&mut subscriber, &subscribe_request, 1).expect("Failed to subscribe");
+    assert_eq!(responses[0]["action"], "subscribeResponse");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": { "url": url, "title": "Title", "innerText": "Inner text" },
+        "correlationId": CORRELATION_ID
+    });
+    run_persistent(&db_path, &[save_request], 1);
+
+    // Read on another thread, so that a missing event fails the test rather than hanging it.
+    let mut stdout = subscriber.stdout.take().expect("Failed to open stdout");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || sender.send(base::read_response(&mut stdout).ok()));
+    let event = receiver
+        .recv_timeout(std::time::Duration::from_secs(10))
+        .expect("Timed out waiting for event")
+        .expect("Failed to read event");
+
+    let expected = json!({
+        "version": VERSION,
+        "action": "siteChanged",
+        "payload": { "url": url, "change": "saved" },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(event, expected);
+
+    drop(subscriber.stdin.take());
+    let status = subscriber.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+}
+
+#[test]
+fn test_subscribe_after_prune() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let save = |url: &str| {
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": { "url": url, "title": "Title", "innerText": "Inner text" },
+            "correlationId": CORRELATION_ID
+        })
+    };
+
+    let mut subscriber = Command::new(base::exe())
+        .arg("--no-daemon")
+        .arg("--db")
+        .arg(&db_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let subscribe_request = json!({
+        "version": VERSION,
+        "action": "subscribeRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    base::exchange(&mut subscriber, &subscribe_request, 1).expect("Failed to subscribe");
+
+    // Read on another thread, so that a missing event fails the test rather than hanging it.
+    let mut stdout = subscriber.stdout.take().expect("Failed to open stdout");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = base::read_response(&mut stdout) {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    let next_event = || {
+        receiver
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("Timed out waiting for event")
+    };
+
+    run_persistent(&db_path, &[save("https://example.com/a")], 1);
+    assert_eq!(next_event()["payload"]["url"], "https://example.com/a");
+
+    // The next process to open the database prunes every change, before saving another site.
+    {
+        let connection = rusqlite::Connection::open(&db_path).expect("Failed to open database");
+        connection
+            .execute(
+                "UPDATE changes SET changed_at = datetime('now', '-2 days')",
+                [],
+            )
+            .expect("Failed to age changes");
+    }
+    run_persistent(&db_path, &[save("https://example.com/b")], 1);
+    assert_eq!(next_event()["payload"]["url"], "https://example.com/b");
+
+    drop(subscriber.stdin.take());
+    let status = subscriber.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+/// Waits for up to ten seconds for `path` to exist, or not to exist.
+fn wait_for_path(path: &std::path::Path, exists: bool) {
+    for _ in 0..200 {
+        if path.exists() == exists {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    panic!("Timed out waiting for {}", path.display());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let socket_path = dir.path().join("db.sqlite3.sock");
+
+    let mut daemon = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .args(["serve", "--idle-timeout", "2"])
+        .spawn()
+        .expect("Failed to start daemon");
+    wait_for_path(&socket_path, true);
+
+    let status = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("serve")
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to run serve");
+    assert!(!status.success());
+
+    let mut subscriber = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let subscribe_request = json!({
+        "version": VERSION,
+        "action": "subscribeRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let responses =
+        base::exchange(&mut subscriber, &subscribe_request, 1).expect("Failed to subscribe");
+    assert_eq!(responses[0]["action"], "subscribeResponse");
+
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": { "url": url, "title": "Title", "innerText": "Foo bar baz quux" },
+        "correlationId": CORRELATION_ID
+    });
+    let args = [std::ffi::OsStr::new("--db"), db_path.as_os_str()];
+    let responses = run_with_args(args, &[save_request], 1);
+    assert_eq!(responses[0]["action"], "saveResponse");
+
+    let stdout = subscriber.stdout.as_mut().expect("Failed to open stdout");
+    let event = base::read_response(stdout).expect("Failed to read event");
+    assert_eq!(event["action"], "siteChanged");
+    assert_eq!(event["payload"], json!({ "url": url, "change": "saved" }));
+    drop(subscriber.stdin.take());
+    let status = subscriber.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": {
+            "query": "quux",
+            "pageNum": 0,
+            "pageLength": 10,
+        },
+        "correlationId": CORRELATION_ID
+    });
+    let responses = run_with_args(args, &[search_request], 2);
+    assert_eq!(responses[1]["payload"]["url"], url);
+
+    let backup_path = dir.path().join("backup.sqlite3");
+    let status = Command::new(base::exe())
+        .args(args)
+        .arg("backup")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run backup");
+    assert!(status.success());
+    let status = Command::new(base::exe())
+        .args(args)
+        .arg("restore")
+        .arg(&backup_path)
+        .status()
+        .expect("Failed to run restore");
+    assert!(!status.success());
+
+    // The daemon exits once it has had no clients for the idle timeout.
+    let status = daemon.wait().expect("Failed to wait for daemon");
+    assert!(status.success());
+    assert!(!socket_path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_slow_client() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let socket_path = dir.path().join("db.sqlite3.sock");
+
+    // Large sites, so that a few lists of them fill a socket's buffer.
+    let title = "Title ".repeat(170);
+    let save_requests: Vec<Value> = (0..100)
+        .map(|i| {
+            json!({
+                "version": VERSION,
+                "action": "saveRequest",
+                "payload": {
+                    "url": format!("https://example.com/{i}/{}", "a".repeat(1000)),
+                    "title": title,
+                    "innerText": "Foo bar baz quux"
+                },
+                "correlationId": CORRELATION_ID
+            })
+        })
+        .collect();
+    run_persistent(&db_path, &save_requests, save_requests.len());
+
+    let mut daemon = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .args(["serve", "--idle-timeout", "2"])
+        .spawn()
+        .expect("Failed to start daemon");
+    wait_for_path(&socket_path, true);
+
+    // A client that sends requests but never reads their responses.
+    let mut slow =
+        std::os::unix::net::UnixStream::connect(&socket_path).expect("Failed to connect to daemon");
+    let list_request = json!({
+        "version": VERSION,
+        "action": "listRequest",
+        "payload": { "pageNum": 0, "pageLength": 100 },
+        "correlationId": CORRELATION_ID
+    });
+    let count = 200;
+    for _ in 0..count {
+        base::write_request(&mut slow, &list_request).expect("Failed to write request");
+    }
+
+    // The other clients are still answered.
+    let search_request = json!({
+        "version": VERSION,
+        "action": "searchRequest",
+        "payload": { "query": "quux", "pageNum": 0, "pageLength": 1 },
+        "correlationId": CORRELATION_ID
+    });
+    let args = [std::ffi::OsStr::new("--db"), db_path.as_os_str()];
+    let responses = run_with_args(args, &[search_request], 2);
+    assert_eq!(responses[0]["action"], "searchResponseHeader");
+
+    // The slow client is disconnected once too many responses are waiting for it.
+    let mut read = 0;
+    while base::read_response(&mut slow).is_ok() {
+        read += 1;
+    }
+    assert!(read < count * 101);
+    drop(slow);
+
+    let status = daemon.wait().expect("Failed to wait for daemon");
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_uploads() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let socket_path = dir.path().join("db.sqlite3.sock");
+    let request = |action: &str, payload: Value| {
+        json!({
+            "version": VERSION,
+            "action": action,
+            "payload": payload,
+            "correlationId": CORRELATION_ID
+        })
+    };
+
+    let mut daemon = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .args(["serve", "--idle-timeout", "2"])
+        .spawn()
+        .expect("Failed to start daemon");
+    wait_for_path(&socket_path, true);
+    let client = || {
+        Command::new(base::exe())
+            .arg("--db")
+            .arg(&db_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start child process")
+    };
+    let (mut owner, mut other) = (client(), client());
+
+    let begin = request(
+        "saveBeginRequest",
+        json!({ "url": "https://example.com/", "title": "Title" }),
+    );
+    let responses = base::exchange(&mut owner, &begin, 1).expect("Failed to begin upload");
+    assert_eq!(responses[0]["action"], "saveBeginResponse");
+    let upload_id = responses[0]["payload"]["uploadId"].clone();
+    let chunk = request(
+        "saveChunkRequest",
+        json!({ "uploadId": upload_id, "index": 0, "text": "Foo bar baz quux" }),
+    );
+    let commit = request(
+        "saveCommitRequest",
+        json!({ "uploadId": upload_id, "count": 1 }),
+    );
+
+    // Another client of the daemon cannot add to or commit the upload.
+    for message in [&chunk, &commit] {
+        let responses = base::exchange(&mut other, message, 1).expect("Failed to send request");
+        assert_eq!(responses[0]["payload"]["kind"], "invalidUpload");
+    }
+
+    let responses = base::exchange(&mut owner, &chunk, 1).expect("Failed to add chunk");
+    assert_eq!(responses[0]["action"], "saveChunkResponse");
+    let responses = base::exchange(&mut owner, &commit, 1).expect("Failed to commit upload");
+    assert_eq!(responses[0]["action"], "saveResponse");
+
+    for mut child in [owner, other] {
+        drop(child.stdin.take());
+        let status = child.wait().expect("Failed to wait for child process");
+        assert!(status.success());
+    }
+    let status = daemon.wait().expect("Failed to wait for daemon");
+    assert!(status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_started_by_host() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let socket_path = dir.path().join("db.sqlite3.sock");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": "https://en.wikipedia.org/wiki/Foobar",
+            "title": "Title",
+            "innerText": "Foo bar baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    // The daemon is given the host's settings, including values that start with `-`.
+    let mut child = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg(r"--query-regex=-|\W+")
+        .env("NOEMATIC_IDLE_TIMEOUT", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let responses = base::exchange(&mut child, &save_request, 1).expect("Failed to save");
+    assert_eq!(responses[0]["action"], "saveResponse");
+    assert!(socket_path.exists());
+    drop(child.stdin.take());
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+
+    wait_for_path(&socket_path, false);
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("stats")
+        .output()
+        .expect("Failed to run stats");
+    assert!(output.status.success());
+    let stats: Value = serde_json::from_slice(&output.stdout).expect("Failed to parse stats");
+    assert_eq!(stats["totalSites"], 1);
+}
+
+#[cfg(not(unix))]
+#[test]
+fn test_serve_unsupported() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let output = Command::new(base::exe())
+        .arg("--db")
+        .arg(&db_path)
+        .arg("serve")
+        .output()
+        .expect("Failed to run serve");
+    assert!(!output.status.success());
+}
+
+/// Starts the HTTP server on an unused port, with its config and data in `dir`, returning it and
+/// the address it listens on.
+fn start_http(dir: &std::path::Path) -> (std::process::Child, String) {
+    let mut server = Command::new(base::exe())
+        .arg("--db")
+        .arg(dir.join("db.sqlite3"))
+        .args(["http", "--bind", "127.0.0.1:0"])
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        // Small enough that a body over it is quick to send.
+        .env("NOEMATIC_MAX_REQUEST_SIZE", "65536")
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start server");
+    let stdout = server.stdout.take().expect("Failed to open stdout");
+    let mut line = String::new();
+    std::io::BufRead::read_line(&mut std::io::BufReader::new(stdout), &mut line)
+        .expect("Failed to read address");
+    let addr = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .expect("Missing address");
+    (server, addr.to_string())
+}
+
+#[test]
+fn test_http() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    // An empty token is replaced rather than accepted.
+    let token_path = dir.path().join("data/noematic/http-token");
+    std::fs::create_dir_all(dir.path().join("data/noematic")).expect("Failed to create data dir");
+    std::fs::write(&token_path, "\n").expect("Failed to write token");
+    let (mut server, addr) = start_http(dir.path());
+    let addr = addr.as_str();
+    let token = std::fs::read_to_string(&token_path).expect("Failed to read token");
+    let bearer = token.trim();
+    assert!(!bearer.is_empty());
+    let token = Some(bearer);
+
+    let request = |method, target, token: Option<&str>, body| {
+        let authorization = token.map(|token| format!("Bearer {token}"));
+        let headers: Vec<(&str, &str)> = authorization
+            .iter()
+            .map(|value| ("Authorization", value.as_str()))
+            .collect();
+        let (status, body) = base::http_request(addr, method, target, &headers, body)
+            .expect("Failed to send request");
+        let body: Option<Value> =
+            (!body.is_empty()).then(|| serde_json::from_str(&body).expect("Invalid JSON"));
+        (status, body)
+    };
+
+    let (status, _) = request("GET", "/search?q=quux", None, None);
+    assert_eq!(status, 401);
+    let (status, _) = request("GET", "/search?q=quux", Some("wrong"), None);
+    assert_eq!(status, 401);
+    let (status, _) = request("GET", "/search?q=quux", Some(""), None);
+    assert_eq!(status, 401);
+
+    let site = json!({ "url": url, "title": "Title", "innerText": "Foo bar baz quux" });
+    let (status, body) = request("POST", "/sites", token, Some(&site));
+    assert_eq!((status, body), (204, None));
+
+    let (status, body) = request("GET", "/search?q=baz+quux&pageLength=5", token, None);
+    let expected = json!({
+        "query": "baz quux",
+        "pageNum": 0,
+        "pageLength": 1,
+        "hasMore": false,
+        "sites": [{
+            "url": url,
+            "title": "Title",
+            "snippet": "Foo bar <b>baz</b> <b>quux</b>",
+            "passage": { "start": 0, "end": 16 },
+            "status": "unread",
+        }],
+    });
+    assert_eq!((status, body), (200, Some(expected)));
+
+    let (status, _) = request("GET", "/search", token, None);
+    assert_eq!(status, 400);
+    let (status, _) = request("GET", "/search?q=quux&page=first", token, None);
+    assert_eq!(status, 400);
+    let (status, _) = request("PUT", "/sites", token, None);
+    assert_eq!(status, 405);
+    let (status, _) = request("GET", "/missing", token, None);
+    assert_eq!(status, 404);
+
+    // A body is no larger than the largest request, whether or not its length is given.
+    let large_site = json!({ "url": url, "title": "Title", "innerText": "a".repeat(65536) });
+    let (status, body) = request("POST", "/sites", token, Some(&large_site));
+    assert_eq!(status, 413);
+    assert_eq!(
+        body.expect("Missing body")["error"],
+        "Body too large: over 65536 bytes"
+    );
+    let mut stream = std::net::TcpStream::connect(addr).expect("Failed to connect");
+    let large_site = large_site.to_string();
+    write!(
+        stream,
+        "POST /sites HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\
+         Authorization: Bearer {bearer}\r\nTransfer-Encoding: chunked\r\n\r\n\
+         {:x}\r\n{large_site}\r\n0\r\n\r\n",
+        large_site.len()
+    )
+    .expect("Failed to send request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("Failed to read response");
+    assert!(response.starts_with("HTTP/1.1 413"));
+
+    let target = format!(
+        "/sites?url={}",
+        "https%3A%2F%2Fen.wikipedia.org%2Fwiki%2FFoobar"
+    );
+    let (status, _) = request("DELETE", &target, token, None);
+    assert_eq!(status, 204);
+    let (status, body) = request("GET", "/search?q=quux", token, None);
+    assert_eq!(status, 200);
+    assert_eq!(body.expect("Missing body")["sites"], json!([]));
+
+    server.kill().expect("Failed to stop server");
+    server.wait().expect("Failed to wait for server");
+}
+
+#[test]
+fn test_opensearch() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let save_request = json!({
+        "version": VERSION,
+        "action": "saveRequest",
+        "payload": {
+            "url": url,
+            "title": "Title <script>",
+            "innerText": "Foo <b>bar</b> baz quux"
+        },
+        "correlationId": CORRELATION_ID
+    });
+    run_persistent(&dir.path().join("db.sqlite3"), &[save_request], 1);
+    let (mut server, addr) = start_http(dir.path());
+    let addr = addr.as_str();
+    let get = |target| base::http_request(addr, "GET", target, &[], None).expect("Failed to get");
+
+    let (status, body) = get("/opensearch.xml");
+    assert_eq!(status, 200);
+    let template = format!("template=\"http://{addr}/results?q={{searchTerms}}\"");
+    assert!(body.contains(&template), "{body}");
+
+    let (status, body) = get("/suggest?q=quux");
+    assert_eq!(status, 200);
+    let suggestions: Value = serde_json::from_str(&body).expect("Invalid JSON");
+    let expected = json!([
+        "quux",
+        ["Title <script>"],
+        ["Foo <b>bar</b> baz quux"],
+        [url]
+    ]);
+    assert_eq!(suggestions, expected);
+
+    let (status, body) = get("/suggest?q=");
+    assert_eq!(status, 200);
+    assert_eq!(body, r#"["",[],[],[]]"#);
+
+    let (status, body) = get("/results?q=quux");
+    assert_eq!(status, 200);
+    assert!(body.contains(&format!(r#"<a href="{url}">Title &lt;script&gt;</a>"#)));
+    // Only matches are highlighted, not tags in the text.
+    assert!(body.contains("Foo &lt;b&gt;bar&lt;/b&gt; baz <b>quux</b>"));
+    assert!(!body.contains("<script>"));
+
+    let headers = [("Host", "attacker.example")];
+    let (status, _) =
+        base::http_request(addr, "GET", "/results?q=quux", &headers, None).expect("Failed to get");
+    assert_eq!(status, 403);
+
+    server.kill().expect("Failed to stop server");
+    server.wait().expect("Failed to wait for server");
+}
+
+#[test]
+fn test_rpc() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let mut child = Command::new(base::exe())
+        .arg("--db")
+        .arg(dir.path().join("db.sqlite3"))
+        .args(["rpc", "--max-page-length", "5"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let mut stdin = child.stdin.take().expect("Failed to open stdin");
+    let mut stdout = BufReader::new(child.stdout.take().expect("Failed to open stdout"));
+    let mut send = |line: &str| {
+        writeln!(stdin, "{line}").expect("Failed to write request");
+    };
+    let mut receive = || {
+        let mut line = String::new();
+        stdout
+            .read_line(&mut line)
+            .expect("Failed to read response");
+        serde_json::from_str::<Value>(&line).expect("Invalid JSON")
+    };
+
+    send(
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#,
+    );
+    let response = receive();
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(response["result"]["serverInfo"]["name"], "noematic");
+
+    // Notifications have no response, so the next response is to the next request.
+    send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+    send(r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#);
+    let response = receive();
+    let names: Vec<&Value> = response["result"]["tools"]
+        .as_array()
+        .expect("Missing tools")
+        .iter()
+        .map(|tool| &tool["name"])
+        .collect();
+    assert_eq!(names, ["search", "get", "save", "remove"]);
+    // The schema allows only the page lengths that are accepted.
+    assert_eq!(
+        response["result"]["tools"][0]["inputSchema"]["properties"]["pageLength"],
+        json!({ "type": "integer", "minimum": 1, "maximum": 5, "default": 5 })
+    );
+
+    let save = json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "save",
+        "params": { "url": url, "title": "Title", "innerText": "Foo bar baz quux" }
+    });
+    send(&save.to_string());
+    assert_eq!(
+        receive(),
+        json!({ "jsonrpc": "2.0", "id": 3, "result": {} })
+    );
+
+    let search = json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "tools/call",
+        "params": { "name": "search", "arguments": { "query": "quux" } }
+    });
+    send(&search.to_string());
+    let response = receive();
+    assert_eq!(response["result"]["isError"], false);
+    let text = response["result"]["content"][0]["text"]
+        .as_str()
+        .expect("Missing text");
+    let result: Value = serde_json::from_str(text).expect("Invalid JSON");
+    assert_eq!(result["sites"][0]["url"], url);
+    assert_eq!(result["sites"][0]["snippet"], "Foo bar baz <b>quux</b>");
+
+    let get = json!({ "jsonrpc": "2.0", "id": 5, "method": "get", "params": { "url": url } });
+    send(&get.to_string());
+    let response = receive();
+    assert_eq!(response["result"]["innerText"], "Foo bar baz quux");
+    assert_eq!(response["result"]["status"], "unread");
+
+    let remove = json!({
+        "jsonrpc": "2.0",
+        "id": 6,
+        "method": "tools/call",
+        "params": { "name": "remove", "arguments": { "url": url } }
+    });
+    send(&remove.to_string());
+    assert_eq!(receive()["result"]["isError"], false);
+    send(&get.to_string());
+    assert_eq!(receive()["result"], Value::Null);
+
+    send(r#"{"jsonrpc":"2.0","id":7,"method":"missing"}"#);
+    assert_eq!(receive()["error"]["code"], -32601);
+    send(r#"{"jsonrpc":"2.0","id":8,"method":"get","params":{}}"#);
+    assert_eq!(receive()["error"]["code"], -32602);
+    send(
+        r#"{"jsonrpc":"2.0","id":9,"method":"tools/call","params":{"name":"get","arguments":{}}}"#,
+    );
+    assert_eq!(receive()["result"]["isError"], true);
+    send("{");
+    let response = receive();
+    assert_eq!(response["error"]["code"], -32700);
+    assert_eq!(response["id"], Value::Null);
+    send(r#"[{"jsonrpc":"2.0","id":10,"method":"ping"},{"jsonrpc":"2.0","method":"ping"}]"#);
+    assert_eq!(
+        receive(),
+        json!([{ "jsonrpc": "2.0", "id": 10, "result": {} }])
+    );
+
+    drop(stdin);
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+}
+
+#[test]
+fn test_hello() {
+    let hello_request = |min: &str, max: &str| {
+        json!({
+            "version": max,
+            "action": "helloRequest",
+            "payload": {
+                "versions": { "min": min, "max": max },
+                "capabilities": ["subscribe", "futureFeature"]
+            },
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let search_request = |version: &str| {
+        json!({
+            "version": version,
+            "action": "searchRequest",
+            "payload": { "query": "foo", "pageNum": 0, "pageLength": 10 },
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let supported = json!({ "min": VERSION, "max": VERSION });
+    let requests = [
+        hello_request("0.1.0", "0.3.0"),
+        hello_request("0.2.0", "0.3.0"),
+        search_request("0.2.0"),
+        search_request("0.1.7"),
+        hello_request("0.1.3", "0.1.9"),
+        hello_request("0.1.3", "0.2.0"),
+    ];
+    let responses = run_with_args([COMMAND_ARG], &requests, 6);
+
+    let expected = json!({
+        "version": VERSION,
+        "action": "helloResponse",
+        "payload": {
+            "version": VERSION,
+            "versions": supported,
+            "capabilities": ["subscribe", "responseChunk"]
+        },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(responses[0], expected);
+
+    let expected = json!({
+        "version": VERSION,
+        "action": "errorResponse",
+        "payload": {
+            "kind": "incompatibleVersion",
+            "message": "Incompatible version: 0.2.0 to 0.3.0",
+            "supported": supported
+        },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(responses[1], expected);
+
+    // The host keeps handling requests after rejecting one.
+    let expected = json!({
+        "version": VERSION,
+        "action": "errorResponse",
+        "payload": {
+            "kind": "incompatibleVersion",
+            "message": "Incompatible version: 0.2.0",
+            "supported": supported
+        },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(responses[2], expected);
+
+    // A newer patch version is compatible.
+    assert_eq!(responses[3]["action"], "searchResponseHeader");
+    assert_eq!(responses[3]["version"], "0.1.7");
+
+    // So a range that starts after the expected version may still be compatible with it.
+    assert_eq!(responses[4]["action"], "helloResponse");
+    assert_eq!(responses[4]["payload"]["version"], "0.1.9");
+
+    // Though not if neither its end nor the expected version is accepted.
+    assert_eq!(responses[5]["action"], "errorResponse");
+    assert_eq!(responses[5]["payload"]["kind"], "incompatibleVersion");
+}
+
+#[test]
+fn test_capabilities() {
+    let request = json!({
+        "version": VERSION,
+        "action": "capabilitiesRequest",
+        "payload": {},
+        "correlationId": CORRELATION_ID
+    });
+    let args = [
+        COMMAND_ARG,
+        "--max-page-length",
+        "50",
+        "--max-request-size",
+        "4096",
+    ];
+    let responses = run_with_args(args, &[request], 1);
+
+    let response = &responses[0];
+    assert_eq!(response["action"], "capabilitiesResponse");
+    let payload = &response["payload"];
+    assert_eq!(payload["hostVersion"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(payload["schemaVersion"], "0.6.0");
+    assert_eq!(payload["ftsTokenizer"], "unicode61");
+    assert_eq!(payload["requestActions"], json!(schema_actions("Request")));
+    let features = json!(if cfg!(feature = "encryption") {
+        vec!["encryption"]
+    } else {
+        vec![]
+    });
+    assert_eq!(payload["features"], features);
+    let expected = json!({
+        "maxRequestSize": 4096,
+        "maxResponseSize": 1024 * 1024,
+        "maxPageLength": 50
+    });
+    assert_eq!(payload["limits"], expected);
+}
+
+#[test]
+fn test_request_too_large() {
+    let save_request = |inner_text: &str| {
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": {
+                "url": "https://en.wikipedia.org/wiki/Foobar",
+                "title": "Title",
+                "innerText": inner_text
+            },
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let requests = [save_request(&"x".repeat(2048)), save_request("Inner text")];
+    let responses = run_with_args([COMMAND_ARG, "--max-request-size", "1024"], &requests, 2);
+
+    let size = requests[0].to_string().len();
+    let expected = json!({
+        "version": VERSION,
+        "action": "errorResponse",
+        "payload": {
+            "kind": "requestTooLarge",
+            "message": format!("Request too large: {size} > 1024 bytes"),
+            "size": size,
+            "maxSize": 1024
+        },
+        "correlationId": CORRELATION_ID
+    });
+    assert_eq!(responses[0], expected);
+
+    // The host keeps reading requests after skipping one.
+    assert_eq!(responses[1]["action"], "saveResponse");
+
+    // The extension puts the correlation id after the payload, which may mention one.
+    let inner_text = json!(format!("\"correlationId\": \"\"{}", "x".repeat(2048)));
+    let request = format!(
+        r#"{{"version":"{VERSION}","action":"saveRequest","payload":{{"url":"https://en.wikipedia.org/wiki/Foobar","title":"Title","innerText":{inner_text}}},"correlationId":"{CORRELATION_ID}"}}"#
+    );
+    let mut child = Command::new(base::exe())
+        .args([COMMAND_ARG, "--max-request-size", "1024"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let mut stdin = child.stdin.take().expect("Failed to open stdin");
+    let length = u32::try_from(request.len()).expect("Request too large");
+    stdin
+        .write_all(&length.to_ne_bytes())
+        .and_then(|()| stdin.write_all(request.as_bytes()))
+        .expect("Failed to write request");
+    drop(stdin);
+    let mut stdout = child.stdout.take().expect("Failed to open stdout");
+    let response = base::read_response(&mut stdout).expect("Failed to read response");
+    assert_eq!(response["payload"]["kind"], "requestTooLarge");
+    assert_eq!(response["correlationId"], CORRELATION_ID);
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+}
+
+#[test]
+fn test_response_chunks() {
+    const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    // Quotes are escaped twice in chunks, so the chunks are smaller than they appear.
+    let inner_text = "\"Foo\" bar baz quux. ".repeat(150_000);
+    let requests = [
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": { "url": url, "title": "Title", "innerText": inner_text },
+            "correlationId": CORRELATION_ID
+        }),
+        json!({
+            "version": VERSION,
+            "action": "getRequest",
+            "payload": { "url": url },
+            "correlationId": CORRELATION_ID
+        }),
+    ];
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let noematic = base::exe();
+    let mut child = Command::new(noematic)
+        .arg("--no-daemon")
+        .arg("--db")
+        .arg(dir.path().join("db.sqlite3"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to start child process");
+    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+    for request in &requests {
+        base::write_request(stdin, request).expect("Failed to write request");
+    }
+    drop(child.stdin.take());
+    let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+    let response = base::read_response(stdout).expect("Failed to read response");
+    assert_eq!(response["action"], "saveResponse");
+
+    let mut data = String::new();
+    let mut index = 0;
+    loop {
+        let chunk = base::read_response(stdout).expect("Failed to read response");
+        assert!(chunk.to_string().len() <= MAX_RESPONSE_SIZE);
+        assert_eq!(chunk["action"], "responseChunk");
+        assert_eq!(chunk["correlationId"], CORRELATION_ID);
+        assert_eq!(chunk["payload"]["index"], index);
+        data.push_str(chunk["payload"]["data"].as_str().expect("Missing data"));
+        index += 1;
+        if chunk["payload"]["count"] == index {
+            break;
+        }
+    }
+    assert!(index > 1);
+    let response: Value = serde_json::from_str(&data).expect("Invalid JSON");
+    assert_eq!(response["action"], "getResponse");
+    assert_eq!(response["correlationId"], CORRELATION_ID);
+    assert_eq!(response["payload"]["site"]["innerText"], inner_text);
+
+    let status = child.wait().expect("Failed to wait for child process");
+    assert!(status.success());
+}
+
+#[test]
+fn test_save_chunks() {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let request = |action: &str, payload: Value| {
+        json!({
+            "version": VERSION,
+            "action": action,
+            "payload": payload,
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let search_request = request(
+        "searchRequest",
+        json!({ "query": "quux", "pageNum": 0, "pageLength": 10 }),
+    );
+    let requests = [
+        request("saveBeginRequest", json!({ "url": url, "title": "Title" })),
+        request(
+            "saveChunkRequest",
+            json!({ "uploadId": 1, "index": 1, "text": "baz quux" }),
+        ),
+        request(
+            "saveChunkRequest",
+            json!({ "uploadId": 1, "index": 0, "text": "Foo bar " }),
+        ),
+        // A chunk that is sent again replaces the first.
+        request(
+            "saveChunkRequest",
+            json!({ "uploadId": 1, "index": 0, "text": "Foo bar " }),
+        ),
+        request("saveCommitRequest", json!({ "uploadId": 1, "count": 3 })),
+        search_request.clone(),
+        request("saveCommitRequest", json!({ "uploadId": 1, "count": 2 })),
+        search_request,
+        request("saveCommitRequest", json!({ "uploadId": 1, "count": 2 })),
+        request(
+            "saveChunkRequest",
+            json!({ "uploadId": 2, "index": 0, "text": "Foo" }),
+        ),
+    ];
+    let responses = run_with_args([COMMAND_ARG], &requests, 11);
+
+    let expected = request("saveBeginResponse", json!({ "uploadId": 1 }));
+    assert_eq!(responses[0], expected);
+    for response in &responses[1..4] {
+        assert_eq!(*response, request("saveChunkResponse", json!({})));
+    }
+
+    // Chunk 2 is missing, so nothing is saved.
+    let invalid_upload = |upload_id: u64| {
+        let payload = json!({
+            "kind": "invalidUpload",
+            "message": format!("Unknown or incomplete upload: {upload_id}"),
+            "uploadId": upload_id
+        });
+        request("errorResponse", payload)
+    };
+    assert_eq!(responses[4], invalid_upload(1));
+    assert_eq!(responses[5]["payload"]["pageLength"], 0);
+
+    assert_eq!(responses[6], request("saveResponse", json!({})));
+    assert_eq!(responses[7]["payload"]["pageLength"], 1);
+    assert_eq!(responses[8]["payload"]["url"], url);
+    assert_eq!(
+        responses[8]["payload"]["snippet"],
+        "Foo bar baz <b>quux</b>"
+    );
+
+    // The upload is discarded once it is committed.
+    assert_eq!(responses[9], invalid_upload(1));
+    assert_eq!(responses[10], invalid_upload(2));
+}
+
+#[test]
+fn test_invalid_request() {
+    let request = |action: &str, payload: Value| {
+        json!({
+            "version": VERSION,
+            "action": action,
+            "payload": payload,
+            "correlationId": CORRELATION_ID
+        })
+    };
+    let long_title = "x".repeat(1025);
+    let requests = [
+        request(
+            "saveRequest",
+            json!({ "url": "ftp://example.com/", "title": long_title, "innerText": "Foo" }),
+        ),
+        request(
+            "saveRequest",
+            json!({ "url": "example.com", "title": "Title", "innerText": "Foo" }),
+        ),
+        request(
+            "searchRequest",
+            json!({ "query": " ", "pageNum": 0, "pageLength": 0 }),
+        ),
+        request(
+            "saveRequest",
+            json!({ "url": "https://example.com/", "title": "Title", "innerText": "Foo", "extra": 1 }),
+        ),
+        request(
+            "saveRequest",
+            json!({ "url": "file:///home/user/notes.html", "title": "Notes", "innerText": "Foo" }),
+        ),
+        request(
+            "editAnnotationRequest",
+            json!({ "id": 1, "note": "Note", "quote": { "text": "Foo", "start": 3, "end": 0 } }),
+        ),
+        request("removeRequest", json!({ "url": "ftp://example.com/" })),
+        request(
+            "searchRequest",
+            json!({ "query": "Foo", "pageNum": 0, "pageLength": 101 }),
+        ),
+    ];
+    let responses = run_with_args([COMMAND_ARG], &requests, 8);
+
+    let fields = |response: &Value| {
+        assert_eq!(response["action"], "errorResponse");
+        assert_eq!(response["correlationId"], CORRELATION_ID);
+        assert_eq!(response["payload"]["kind"], "invalidRequest");
+        response["payload"]["fields"]
+            .as_array()
+            .expect("Missing fields")
+            .iter()
+            .map(|error| error["field"].as_str().expect("Missing field").to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(fields(&responses[0]), ["url", "title"]);
+    assert_eq!(
+        responses[0]["payload"]["fields"][0]["message"],
+        "Scheme must be http, https or file"
+    );
+    assert_eq!(fields(&responses[1]), ["url"]);
+    assert_eq!(fields(&responses[2]), ["query", "pageLength"]);
+
+    // Unknown fields are rejected when the request is parsed, before its fields are checked.
+    assert!(fields(&responses[3]).is_empty());
+    let message = responses[3]["payload"]["message"].as_str().unwrap();
+    assert!(message.contains("unknown field `extra`"));
+
+    assert_eq!(responses[4]["action"], "saveResponse");
+    assert_eq!(fields(&responses[5]), ["quote"]);
+
+    // Only the URLs of sites being saved are checked.
+    assert_eq!(responses[6]["action"], "removeResponse");
+    assert_eq!(fields(&responses[7]), ["pageLength"]);
+}
+
+#[test]
+fn test_schema_up_to_date() {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let artifacts = [
+        (vec!["schema"], root.join("schema/messages.schema.json")),
+        (
+            vec!["schema", "--typescript"],
+            root.join("../extension/common/messages.d.ts"),
+        ),
+    ];
+    for (args, path) in artifacts {
+        let output = Command::new(base::exe())
+            .args(&args)
+            .output()
+            .expect("Failed to run child process");
+        assert!(output.status.success());
+        let expected = std::fs::read_to_string(&path).expect("Failed to read artifact");
+        let actual = String::from_utf8(output.stdout).expect("Failed to decode output");
+        assert!(
+            actual == expected,
+            "{} is out of date, run `make schema` to regenerate it",
+            path.display()
+        );
+    }
+}
+
+/// Returns the actions of the messages described by a definition in the schema.
+fn schema_actions(definition: &str) -> Vec<String> {
+    let schema = noematic::schema::json_schema();
+    schema["$defs"][definition]["oneOf"]
+        .as_array()
+        .expect("Missing variants")
+        .iter()
+        .map(|variant| variant["properties"]["action"]["const"].to_string())
+        .map(|action| action.trim_matches('"').to_string())
+        .collect()
+}
+
+fn message(action: &str, payload: Value) -> Value {
+    json!({
+        "version": VERSION,
+        "action": action,
+        "payload": payload,
+        "correlationId": CORRELATION_ID
+    })
+}
+
+fn request_samples() -> Vec<Value> {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let quote = json!({ "text": "bar", "start": 4, "end": 7 });
+    vec![
+        message(
+            "saveRequest",
+            json!({ "url": url, "title": "Foobar", "innerText": "Foo bar baz quux" }),
+        ),
+        message("removeRequest", json!({ "url": url })),
+        message(
+            "searchRequest",
+            json!({ "query": "quux", "pageNum": 0, "pageLength": 10, "status": null }),
+        ),
+        message(
+            "addAnnotationRequest",
+            json!({ "url": url, "note": "Note", "quote": quote }),
+        ),
+        message(
+            "editAnnotationRequest",
+            json!({ "id": 1, "note": "Note", "quote": null }),
+        ),
+        message("removeAnnotationRequest", json!({ "id": 1 })),
+        message("listAnnotationsRequest", json!({ "url": url })),
+        message("setStatusRequest", json!({ "url": url, "status": "read" })),
+        message(
+            "listRequest",
+            json!({ "pageNum": 1, "pageLength": 10, "status": "unread", "trashed": true }),
+        ),
+        message("restoreRequest", json!({ "url": url })),
+        message("emptyTrashRequest", json!({})),
+        message("statsRequest", json!({})),
+        message("backupRequest", json!({ "path": "/tmp/backup.sqlite3" })),
+        message(
+            "maintenanceRequest",
+            json!({ "rebuild": true, "optimize": false }),
+        ),
+        message("configRequest", json!({})),
+        message("subscribeRequest", json!({})),
+        message("getRequest", json!({ "url": url })),
+        message(
+            "helloRequest",
+            json!({
+                "versions": { "min": "0.1.0", "max": "0.2.0" },
+                "capabilities": ["subscribe"]
+            }),
+        ),
+        message("capabilitiesRequest", json!({})),
+        message("saveBeginRequest", json!({ "url": url, "title": "Foobar" })),
+        message(
+            "saveChunkRequest",
+            json!({ "uploadId": 1, "index": 0, "text": "Foo bar" }),
+        ),
+        message("saveCommitRequest", json!({ "uploadId": 1, "count": 1 })),
+        message("cancelRequest", json!({ "correlationId": "search" })),
+    ]
+}
+
+fn response_samples() -> Vec<Value> {
+    let url = "https://en.wikipedia.org/wiki/Foobar";
+    let timestamp = "2024-01-01 00:00:00";
+    let findings = json!({
+        "integrityCheck": ["ok"],
+        "ftsIntegrityCheck": [{ "table": "sites_fts", "message": "malformed" }],
+        "sites": 2,
+        "indexedSites": 1,
+        "unindexedSites": 1,
+        "sitesWithoutPassages": 0,
+        "healthy": false
+    });
+    vec![
+        message("saveResponse", json!({})),
+        message("removeResponse", json!({})),
+        message(
+            "searchResponseHeader",
+            json!({ "query": "quux", "pageNum": 0, "pageLength": 1, "hasMore": false }),
+        ),
+        message(
+            "searchResponseSite",
+            json!({
+                "url": url,
+                "title": "Foobar",
+                "snippet": "Foo bar baz <b>quux</b>",
+                "passage": { "start": 0, "end": 16 },
+                "status": "unread"
+            }),
+        ),
+        message(
+            "addAnnotationResponse",
+            json!({
+                "annotation": {
+                    "id": 1,
+                    "url": url,
+                    "note": "Note",
+                    "quote": { "text": "bar", "start": 4, "end": 7 },
+                    "createdAt": timestamp,
+                    "updatedAt": timestamp
+                }
+            }),
+        ),
+        message("editAnnotationResponse", json!({ "annotation": null })),
+        message("removeAnnotationResponse", json!({})),
+        message("listAnnotationsResponse", json!({ "annotations": [] })),
+        message("setStatusResponse", json!({})),
+        message(
+            "listResponseHeader",
+            json!({ "pageNum": 0, "pageLength": 1, "hasMore": true }),
+        ),
+        message(
+            "listResponseSite",
+            json!({
+                "url": url,
+                "title": "Foobar",
+                "status": "archived",
+                "createdAt": timestamp,
+                "updatedAt": timestamp,
+                "statusUpdatedAt": null
+            }),
+        ),
+        message("restoreResponse", json!({})),
+        message("emptyTrashResponse", json!({ "removed": 2 })),
+        message(
+            "statsResponse",
+            json!({
+                "totalSites": 1,
+                "statusCounts": { "unread": 1, "read": 0, "archived": 0 },
+                "trashedSites": 0,
+                "totalTextBytes": 16,
+                "databaseSize": 4096,
+                "ftsIndexSize": 1024,
+                "oldestCreatedAt": timestamp,
+                "newestCreatedAt": null,
+                "topDomains": [{ "domain": "en.wikipedia.org", "count": 1 }],
+                "schemaVersion": "0.5.0"
+            }),
+        ),
+        message("backupResponse", json!({})),
+        message(
+            "maintenanceResponse",
+            json!({ "before": findings, "after": null, "rebuilt": false, "optimized": true }),
+        ),
+        message(
+            "configResponse",
+            json!({
+                "configFile": null,
+                "profile": "work",
+                "db": "/tmp/db.sqlite3",
+                "keyFile": null,
+                "trashRetentionDays": 30,
+                "queryRegex": "\\W+",
+                "snippetTokens": 40,
+                "maxPageLength": 100,
+                "maxRequestSize": 67_108_864
+            }),
+        ),
+        message("subscribeResponse", json!({})),
+        message(
+            "getResponse",
+            json!({
+                "site": {
+                    "url": url,
+                    "title": "Foobar",
+                    "innerText": "Foo bar baz quux",
+                    "status": "read",
+                    "createdAt": timestamp,
+                    "updatedAt": timestamp,
+                    "statusUpdatedAt": timestamp
+                }
+            }),
+        ),
+        message(
+            "helloResponse",
+            json!({
+                "version": "0.1.0",
+                "versions": { "min": "0.1.0", "max": "0.1.0" },
+                "capabilities": ["subscribe", "responseChunk"]
+            }),
+        ),
+        message(
+            "capabilitiesResponse",
+            json!({
+                "hostVersion": "0.1.0",
+                "schemaVersion": "0.5.0",
+                "requestActions": ["saveRequest"],
+                "ftsTokenizer": "unicode61",
+                "features": [],
+                "limits": { "maxRequestSize": 4096, "maxResponseSize": 1_048_576, "maxPageLength": 50 }
+            }),
+        ),
+        message(
+            "errorResponse",
+            json!({
+                "kind": "invalidRequest",
+                "message": "Invalid request: url: Scheme must be http, https or file",
+                "fields": [{ "field": "url", "message": "Scheme must be http, https or file" }]
+            }),
+        ),
+        message(
+            "responseChunk",
+            json!({ "index": 0, "count": 2, "data": "{\"version\":" }),
+        ),
+        message("saveBeginResponse", json!({ "uploadId": 1 })),
+        message("saveChunkResponse", json!({})),
+        message(
+            "cancelResponse",
+            json!({ "correlationId": "search", "status": "cancelled" }),
+        ),
+        message(
+            "siteChanged",
+            json!({ "url": url, "change": "statusChanged" }),
+        ),
+    ]
+}
+
+#[test]
+fn test_encoding_round_trip() {
+    use noematic::{
+        encoding::Encoding,
+        message::{Request, Response},
+    };
+
+    let requests = request_samples();
+    let responses = response_samples();
+    let actions = |samples: &[Value]| -> Vec<String> {
+        samples
+            .iter()
+            .map(|sample| sample["action"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(actions(&requests), schema_actions("Request"));
+    assert_eq!(actions(&responses), schema_actions("Response"));
+
+    for encoding in [Encoding::Json, Encoding::MessagePack, Encoding::Cbor] {
+        for sample in &requests {
+            let request: Request = serde_json::from_value(sample.clone()).unwrap();
+            let bytes = encoding.encode(&request).unwrap();
+            let decoded: Request = serde_json::from_value(encoding.decode(&bytes).unwrap())
+                .unwrap_or_else(|err| panic!("{encoding}: {}: {err}", sample["action"]));
+            assert_eq!(
+                serde_json::to_value(decoded).unwrap(),
+                *sample,
+                "{encoding}"
+            );
+        }
+        for sample in &responses {
+            let response: Response = serde_json::from_value(sample.clone()).unwrap();
+            let bytes = encoding.encode(&response).unwrap();
+            let decoded: Response = serde_json::from_value(encoding.decode(&bytes).unwrap())
+                .unwrap_or_else(|err| panic!("{encoding}: {}: {err}", sample["action"]));
+            assert_eq!(
+                serde_json::to_value(decoded).unwrap(),
+                *sample,
+                "{encoding}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_binary_encodings() {
+    use noematic::encoding::Encoding;
+
+    // Larger than a JSON response may be, so that it would be split into chunks.
+    let inner_text = "Foo bar baz quux ".repeat(128 * 1024);
+    let requests = [
+        message(
+            "saveRequest",
+            json!({ "url": "https://example.com/", "title": "Title", "innerText": inner_text }),
+        ),
+        message("getRequest", json!({ "url": "https://example.com/" })),
+    ];
+    for encoding in [Encoding::MessagePack, Encoding::Cbor] {
+        let mut child = Command::new(base::exe())
+            .args([COMMAND_ARG, "--encoding", &encoding.to_string()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start child process");
+        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+        for request in &requests {
+            let bytes = encoding.encode(request).unwrap();
+            stdin
+                .write_all(&u32::try_from(bytes.len()).unwrap().to_ne_bytes())
+                .unwrap();
+            stdin.write_all(&bytes).unwrap();
+        }
+        drop(child.stdin.take());
+        let mut stdout = child.stdout.take().expect("Failed to open stdout");
+        let mut read_response = || {
+            let mut length = [0; 4];
+            stdout.read_exact(&mut length).unwrap();
+            let mut bytes = vec![0; u32::from_ne_bytes(length) as usize];
+            stdout.read_exact(&mut bytes).unwrap();
+            encoding.decode(&bytes).unwrap()
+        };
+
+        assert_eq!(read_response(), message("saveResponse", json!({})));
+        let response = read_response();
+        assert_eq!(response["action"], "getResponse", "{encoding}");
+        assert_eq!(response["payload"]["site"]["innerText"], inner_text);
+        assert!(child.wait().unwrap().success());
+    }
+}
+
+/// Sends saves and searches in one batch, some of which share a correlation id, to a host that
+/// handles searches on other threads, with and without a daemon.
+#[test]
+fn test_pipelining() {
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let save = |url: &str, inner_text: &str, correlation_id: &str| {
+        json!({
+            "version": VERSION,
+            "action": "saveRequest",
+            "payload": { "url": url, "title": "Title", "innerText": inner_text },
+            "correlationId": correlation_id
+        })
+    };
+    let search = |query: &str, correlation_id: &str| {
+        json!({
+            "version": VERSION,
+            "action": "searchRequest",
+            "payload": { "query": query, "pageNum": 0, "pageLength": 10 },
+            "correlationId": correlation_id
+        })
+    };
+    for (i, args) in [vec!["--no-daemon"], vec![]].into_iter().enumerate() {
+        let foo = format!("https://example.com/foo/{i}");
+        let bar = format!("https://example.com/bar/{i}");
+        let requests = [
+            save(&foo, &format!("baz{i} quux{i}"), "a"),
+            search(&format!("baz{i}"), "s"),
+            save(&bar, &format!("corge{i} quux{i}"), "b"),
+            search(&format!("corge{i}"), "s"),
+            search(&format!("quux{i}"), "c"),
+        ];
+        let mut child = Command::new(base::exe())
+            .args(args)
+            .arg("--db")
+            .arg(&db_path)
+            .env("NOEMATIC_IDLE_TIMEOUT", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start child process");
+        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+        for request in &requests {
+            base::write_request(stdin, request).expect("Failed to write request");
+        }
+        drop(child.stdin.take());
+        let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+        let responses: Vec<Value> = (0..9)
+            .map(|_| base::read_response(stdout).expect("Failed to read response"))
+            .collect();
+        let status = child.wait().expect("Failed to wait for child process");
+        assert!(status.success());
+
+        let by_id = |correlation_id: &str| -> Vec<(Value, Value)> {
+            responses
+                .iter()
+                .filter(|response| response["correlationId"] == correlation_id)
+                .map(|response| {
+                    let detail = match &response["payload"]["query"] {
+                        Value::Null => response["payload"]["url"].clone(),
+                        query => query.clone(),
+                    };
+                    (response["action"].clone(), detail)
+                })
+                .collect()
+        };
+        assert_eq!(by_id("a"), [(json!("saveResponse"), Value::Null)]);
+        assert_eq!(by_id("b"), [(json!("saveResponse"), Value::Null)]);
+        // The responses to the searches with the same correlation id are in the order of the
+        // searches, and each search sees the saves before it.
+        assert_eq!(
+            by_id("s"),
+            [
+                (json!("searchResponseHeader"), json!(format!("baz{i}"))),
+                (json!("searchResponseSite"), json!(foo)),
+                (json!("searchResponseHeader"), json!(format!("corge{i}"))),
+                (json!("searchResponseSite"), json!(bar)),
+            ]
+        );
+        let c = by_id("c");
+        assert_eq!(c.len(), 3);
+        assert_eq!(
+            c[0],
+            (json!("searchResponseHeader"), json!(format!("quux{i}")))
+        );
+    }
+}
+
+#[test]
+fn test_cancel() {
+    let cancel_request = |search_id: &str, correlation_id: &str| {
+        json!({
+            "version": VERSION,
+            "action": "cancelRequest",
+            "payload": { "correlationId": search_id },
+            "correlationId": correlation_id
+        })
+    };
+
+    // Searches of an in-memory database finish before the next request is read.
+    let responses = run_with_args([COMMAND_ARG], &[cancel_request("search", "cancel")], 1);
+    assert_eq!(responses[0]["action"], "cancelResponse");
+    assert_eq!(responses[0]["correlationId"], "cancel");
+    assert_eq!(
+        responses[0]["payload"],
+        json!({ "correlationId": "search", "status": "notRunning" })
+    );
+
+    // Every passage of many sites matches, so that each search takes far longer than reading
+    // the cancels after it.
+    let dir = tempfile::tempdir().expect("Failed to create temporary directory");
+    let db_path = dir.path().join("db.sqlite3");
+    let inner_text = "Foo bar baz quux ".repeat(1000);
+    let save_requests: Vec<Value> = (0..40)
+        .map(|i| {
+            json!({
+                "version": VERSION,
+                "action": "saveRequest",
+                "payload": {
+                    "url": format!("https://example.com/{i}"),
+                    "title": "Title",
+                    "innerText": inner_text
+                },
+                "correlationId": CORRELATION_ID
+            })
+        })
+        .collect();
+    run_persistent(&db_path, &save_requests, save_requests.len());
+
+    for args in [vec!["--no-daemon"], vec![]] {
+        // More searches than there are threads, so that some are cancelled before they run, and
+        // the others while they run. A cancel with the same correlation id as its search does
+        // not wait for it.
+        let mut requests = Vec::new();
+        for i in 0..8 {
+            requests.push(json!({
+                "version": VERSION,
+                "action": "searchRequest",
+                "payload": { "query": "quux", "pageNum": 0, "pageLength": 10 },
+                "correlationId": format!("search{i}")
+            }));
+        }
+        for i in 0..8 {
+            let correlation_id = if i % 2 == 0 {
+                format!("cancel{i}")
+            } else {
+                format!("search{i}")
+            };
+            requests.push(cancel_request(&format!("search{i}"), &correlation_id));
+        }
+        let mut child = Command::new(base::exe())
+            .args(args)
+            .arg("--db")
+            .arg(&db_path)
+            .env("NOEMATIC_IDLE_TIMEOUT", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start child process");
+        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+        for request in &requests {
+            base::write_request(stdin, request).expect("Failed to write request");
+        }
+        drop(child.stdin.take());
+        let stdout = child.stdout.as_mut().expect("Failed to open stdout");
+        let mut responses = Vec::new();
+        while let Ok(response) = base::read_response(stdout) {
+            responses.push(response);
+        }
+        let status = child.wait().expect("Failed to wait for child process");
+        assert!(status.success());
+
+        // A search that was cancelled sends no responses, and one that was not sends them all.
+        let mut cancelled = 0;
+        for i in 0..8 {
+            let search_id = format!("search{i}");
+            let statuses: Vec<&Value> = responses
+                .iter()
+                .filter(|response| {
+                    response["action"] == "cancelResponse"
+                        && response["payload"]["correlationId"] == search_id.as_str()
+                })
+                .map(|response| &response["payload"]["status"])
+                .collect();
+            assert_eq!(statuses.len(), 1);
+            let actions: Vec<&Value> = responses
+                .iter()
+                .filter(|response| {
+                    response["correlationId"] == search_id.as_str()
+                        && response["action"] != "cancelResponse"
+                })
+                .map(|response| &response["action"])
+                .collect();
+            if statuses[0] == "cancelled" {
+                assert!(actions.is_empty());
+                cancelled += 1;
+            } else {
+                assert_eq!(statuses[0], "notRunning");
+                assert_eq!(actions.len(), 11);
+                assert_eq!(actions[0], "searchResponseHeader");
+            }
+        }
+        assert!(cancelled > 0);
+    }
+}